@@ -0,0 +1,12 @@
+CREATE TABLE issue_delivery_tasks
+(
+    id               uuid        NOT NULL PRIMARY KEY,
+    issue_id         uuid        NOT NULL REFERENCES newsletter_issues (id),
+    subscriber_id    uuid        NOT NULL REFERENCES subscriptions (id),
+    subscriber_email TEXT        NOT NULL,
+    status           TEXT        NOT NULL,
+    attempts         INT         NOT NULL DEFAULT 0,
+    last_error       TEXT        NULL,
+    created_at       timestamptz NOT NULL,
+    completed_at     timestamptz NULL
+);
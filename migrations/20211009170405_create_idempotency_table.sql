@@ -0,0 +1,10 @@
+CREATE TABLE idempotency
+(
+    user_id              uuid        NOT NULL REFERENCES users (id),
+    idempotency_key      TEXT        NOT NULL,
+    response_status_code SMALLINT    NULL,
+    response_headers     JSONB       NULL,
+    response_body        BYTEA       NULL,
+    created_at           timestamptz NOT NULL,
+    PRIMARY KEY (user_id, idempotency_key)
+);
@@ -0,0 +1,13 @@
+BEGIN;
+ALTER TABLE newsletter_issues
+    ADD COLUMN status TEXT NULL;
+UPDATE newsletter_issues
+SET status = 'published'
+WHERE status IS NULL;
+ALTER TABLE newsletter_issues
+    ALTER COLUMN status SET NOT NULL;
+ALTER TABLE newsletter_issues
+    ADD COLUMN scheduled_for timestamptz NULL;
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at DROP NOT NULL;
+COMMIT;
@@ -0,0 +1,2 @@
+ALTER TABLE newsletter_issues
+    ADD COLUMN author_id uuid NULL REFERENCES users (id);
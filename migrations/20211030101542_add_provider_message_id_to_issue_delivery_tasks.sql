@@ -0,0 +1,4 @@
+ALTER TABLE issue_delivery_tasks
+    ADD COLUMN provider_message_id TEXT NULL;
+CREATE INDEX issue_delivery_tasks_provider_message_id_idx
+    ON issue_delivery_tasks (provider_message_id);
@@ -0,0 +1,10 @@
+CREATE TABLE delivery_events
+(
+    id                  uuid        NOT NULL PRIMARY KEY,
+    event               TEXT        NOT NULL,
+    email               TEXT        NOT NULL,
+    provider_message_id TEXT        NULL,
+    subscriber_id       uuid        NULL REFERENCES subscriptions (id),
+    issue_id            uuid        NULL REFERENCES newsletter_issues (id),
+    received_at         timestamptz NOT NULL
+);
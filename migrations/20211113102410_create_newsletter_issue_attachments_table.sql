@@ -0,0 +1,10 @@
+CREATE TABLE newsletter_issue_attachments
+(
+    issue_id     uuid    NOT NULL REFERENCES newsletter_issues (id),
+    position     INTEGER NOT NULL,
+    filename     TEXT    NOT NULL,
+    content_type TEXT    NOT NULL,
+    content_id   TEXT    NULL,
+    content      BYTEA   NOT NULL,
+    PRIMARY KEY (issue_id, position)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE issue_delivery_tasks
+    ADD COLUMN provider TEXT NULL;
@@ -1,4 +1,5 @@
 use std::env;
+use std::time::Duration;
 
 use anyhow::Context;
 use config::{
@@ -11,6 +12,15 @@ use sqlx::postgres::{
     PgSslMode,
 };
 
+use crate::email_client::{
+    CircuitBreakerPolicy,
+    DkimSigner,
+    RateLimit,
+    RetryPolicy,
+    SmtpServer,
+    SmtpTls,
+};
+
 #[derive(Clone, Debug, serde::Deserialize)]
 pub struct Settings {
     pub application: ApplicationSettings,
@@ -18,15 +28,28 @@ pub struct Settings {
     pub email_client: EmailClientSettings,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Derivative, Clone, serde::Deserialize)]
+#[derivative(Debug)]
 pub struct ApplicationSettings {
     pub base_url: String,
+    pub delivery_poll_interval_millis: u64,
+    pub delivery_workers: usize,
     pub host: String,
     pub max_pending_connections: u32,
     pub port: u16,
+    pub scheduler_poll_interval_millis: u64,
+    /// The directory of the templates of the emails sent by the app.
+    pub templates_directory: String,
+    /// The address receiving the `mailto:` unsubscribe requests, if any.
+    pub unsubscribe_mailbox: Option<String>,
+    /// The key signing the unsubscribe links, and hashing the recipients in
+    /// the logs.
+    #[derivative(Debug = "ignore")]
+    pub unsubscribe_secret: String,
 }
 
-#[derive(Derivative, Clone, Debug, serde::Deserialize)]
+#[derive(Derivative, Clone, serde::Deserialize)]
+#[derivative(Debug)]
 pub struct DatabaseSettings {
     pub connect_timeout_seconds: u64,
     pub name: String,
@@ -39,13 +62,155 @@ pub struct DatabaseSettings {
     pub username: String,
 }
 
-#[derive(Derivative, Clone, Debug, serde::Deserialize)]
+#[derive(Derivative, Clone, serde::Deserialize)]
+#[derivative(Debug)]
 pub struct EmailClientSettings {
+    pub backend: EmailBackend,
     pub base_url: String,
+    /// Stop calling the backend for a while once it keeps failing.
+    pub circuit_breaker: Option<CircuitBreakerSettings>,
+    /// The providers taking over, in priority order, while the previous ones
+    /// keep failing.
+    #[serde(default)]
+    pub fallbacks: Vec<EmailProviderSettings>,
+    pub max_batch_size: usize,
+    pub max_concurrent_requests: usize,
+    /// Required by the `outbox` backend.
+    pub outbox: Option<OutboxSettings>,
+    /// The quota of the provider plan, enforced by the `mailjet` backend.
+    pub rate_limit: Option<RateLimitSettings>,
+    /// The address the replies of the recipients are sent to, instead of
+    /// `sender_email`.
+    pub reply_to: Option<String>,
+    pub retry: RetrySettings,
     pub sender_email: String,
+    /// The display name of `sender_email`.
+    pub sender_name: String,
+    /// Required by the `smtp` backend.
+    pub smtp: Option<SmtpSettings>,
     pub timeout_secs: u64,
     #[derivative(Debug = "ignore")]
     pub token: String,
+    pub webhook: WebhookSettings,
+}
+
+/// The Basic credentials of the provider event webhook.
+#[derive(Derivative, Clone, serde::Deserialize)]
+#[derivative(Debug)]
+pub struct WebhookSettings {
+    pub username: String,
+    #[derivative(Debug = "ignore")]
+    pub password: String,
+}
+
+/// The [`EmailSender`](crate::email_client::EmailSender) built from the
+/// `email_client` settings.
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailBackend {
+    /// The Mailjet HTTP API, at `base_url`.
+    Mailjet,
+    /// The SMTP server of the `smtp` settings.
+    Smtp,
+    /// JSON files written to the `outbox` directory, nothing is sent.
+    Outbox,
+}
+
+/// A fallback provider, sharing the sender identity, retries and timeout of
+/// the `email_client` settings.
+#[derive(Derivative, Clone, serde::Deserialize)]
+#[derivative(Debug)]
+pub struct EmailProviderSettings {
+    /// Recorded for each delivery the provider carries.
+    pub name: String,
+    pub backend: EmailBackend,
+    /// Defaults to `email_client.base_url`.
+    pub base_url: Option<String>,
+    pub outbox: Option<OutboxSettings>,
+    pub rate_limit: Option<RateLimitSettings>,
+    pub smtp: Option<SmtpSettings>,
+    /// Defaults to `email_client.token`.
+    #[derivative(Debug = "ignore")]
+    pub token: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct OutboxSettings {
+    pub directory: String,
+}
+
+#[derive(Derivative, Clone, serde::Deserialize)]
+#[derivative(Debug)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    #[derivative(Debug = "ignore")]
+    pub password: Option<String>,
+    /// Sign the messages with DKIM when set.
+    pub dkim: Option<DkimSettings>,
+}
+
+#[derive(Derivative, Clone, serde::Deserialize)]
+#[derivative(Debug)]
+pub struct DkimSettings {
+    pub domain: String,
+    pub selector: String,
+    /// An RSA key in PEM.
+    #[derivative(Debug = "ignore")]
+    pub private_key: String,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct RetrySettings {
+    pub base_delay_millis: u64,
+    /// Fraction of each delay, between 0 and 1, that is randomised.
+    pub jitter: f64,
+    pub max_attempts: u32,
+    pub max_delay_millis: u64,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct CircuitBreakerSettings {
+    pub failure_threshold: u32,
+    pub open_duration_secs: u64,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct RateLimitSettings {
+    pub burst: u32,
+    pub max_wait_millis: u64,
+    pub messages_per_day: Option<u32>,
+    pub messages_per_second: f64,
+}
+
+impl EmailClientSettings {
+    /// The name the deliveries of the primary provider are recorded under.
+    pub fn provider_name(&self) -> &'static str {
+        match self.backend {
+            EmailBackend::Mailjet => "mailjet",
+            EmailBackend::Smtp => "smtp",
+            EmailBackend::Outbox => "outbox",
+        }
+    }
+
+    /// These settings, with the backend of `fallback` instead of their own.
+    pub fn with_provider(&self, fallback: &EmailProviderSettings) -> EmailClientSettings {
+        EmailClientSettings {
+            backend: fallback.backend.clone(),
+            base_url: fallback
+                .base_url
+                .clone()
+                .unwrap_or_else(|| self.base_url.clone()),
+            fallbacks: Vec::new(),
+            outbox: fallback.outbox.clone(),
+            rate_limit: fallback.rate_limit.clone(),
+            smtp: fallback.smtp.clone(),
+            token: fallback.token.clone().unwrap_or_else(|| self.token.clone()),
+            ..self.clone()
+        }
+    }
 }
 
 impl ApplicationSettings {
@@ -54,6 +219,59 @@ impl ApplicationSettings {
     }
 }
 
+impl RetrySettings {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            base_delay: Duration::from_millis(self.base_delay_millis),
+            max_delay: Duration::from_millis(self.max_delay_millis),
+            jitter: self.jitter,
+        }
+    }
+}
+
+impl CircuitBreakerSettings {
+    pub fn circuit_breaker_policy(&self) -> CircuitBreakerPolicy {
+        CircuitBreakerPolicy {
+            failure_threshold: self.failure_threshold,
+            open_duration: Duration::from_secs(self.open_duration_secs),
+        }
+    }
+}
+
+impl RateLimitSettings {
+    pub fn rate_limit(&self) -> RateLimit {
+        RateLimit {
+            messages_per_second: self.messages_per_second,
+            burst: self.burst,
+            messages_per_day: self.messages_per_day,
+            max_wait: Duration::from_millis(self.max_wait_millis),
+        }
+    }
+}
+
+impl SmtpSettings {
+    pub fn smtp_server(&self) -> SmtpServer {
+        SmtpServer {
+            host: self.host.clone(),
+            port: self.port,
+            tls: self.tls,
+            username: self.username.clone(),
+            password: self.password.clone(),
+        }
+    }
+}
+
+impl DkimSettings {
+    pub fn dkim_signer(&self) -> Result<DkimSigner, anyhow::Error> {
+        DkimSigner::new(
+            self.domain.clone(),
+            self.selector.clone(),
+            &self.private_key,
+        )
+    }
+}
+
 impl DatabaseSettings {
     pub fn pgserver_connection_options(&self) -> PgConnectOptions {
         let ssl_mode = if self.require_ssl {
@@ -97,3 +315,77 @@ pub fn load_configuration() -> Result<Settings, anyhow::Error> {
     config.merge(config::Environment::with_prefix("app").separator("__"))?;
     config.try_into().map(Ok)?
 }
+
+#[cfg(test)]
+mod tests {
+    use config::FileFormat;
+
+    use super::*;
+
+    #[test]
+    fn the_secrets_are_not_debug_printed() {
+        let settings = [
+            "[application]",
+            "base_url = \"http://localhost\"",
+            "delivery_poll_interval_millis = 1000",
+            "delivery_workers = 2",
+            "host = \"localhost\"",
+            "max_pending_connections = 128",
+            "port = 8000",
+            "scheduler_poll_interval_millis = 1000",
+            "templates_directory = \"templates\"",
+            "unsubscribe_secret = \"secret-unsubscribe\"",
+            "[database]",
+            "connect_timeout_seconds = 2",
+            "name = \"newsletter\"",
+            "host = \"localhost\"",
+            "max_db_connections = 16",
+            "password = \"secret-database\"",
+            "port = 5432",
+            "require_ssl = false",
+            "username = \"postgres\"",
+            "[email_client]",
+            "backend = \"mailjet\"",
+            "base_url = \"http://localhost\"",
+            "max_batch_size = 50",
+            "max_concurrent_requests = 4",
+            "sender_email = \"newsletter@example.com\"",
+            "sender_name = \"Newsletter\"",
+            "token = \"secret-token\"",
+            "timeout_secs = 10",
+            "[email_client.retry]",
+            "base_delay_millis = 500",
+            "jitter = 0.5",
+            "max_attempts = 3",
+            "max_delay_millis = 10000",
+            "[email_client.webhook]",
+            "username = \"mailjet\"",
+            "password = \"secret-webhook\"",
+            "[email_client.smtp]",
+            "host = \"localhost\"",
+            "port = 587",
+            "tls = \"starttls\"",
+            "username = \"newsletter\"",
+            "password = \"secret-smtp\"",
+            "[email_client.smtp.dkim]",
+            "domain = \"example.com\"",
+            "selector = \"newsletter\"",
+            "private_key = \"secret-dkim\"",
+            "[[email_client.fallbacks]]",
+            "name = \"fallback\"",
+            "backend = \"mailjet\"",
+            "token = \"secret-fallback-token\"",
+        ];
+        let mut config = Config::new();
+        config
+            .merge(File::from_str(&settings.join("\n"), FileFormat::Toml))
+            .unwrap();
+        let settings: Settings = config.try_into().unwrap();
+
+        let debug = format!("{:?}", settings);
+
+        assert!(debug.contains("example.com"));
+        assert!(debug.contains("fallback"));
+        assert!(!debug.contains("secret"));
+    }
+}
@@ -1,5 +1,8 @@
 use std::convert::TryInto;
 use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 use actix_web::dev::Server;
 use actix_web::{
@@ -14,15 +17,31 @@ use url::Url;
 
 use crate::app::configuration::{
     DatabaseSettings,
+    EmailBackend,
     EmailClientSettings,
     Settings,
 };
+use crate::delivery::{
+    run_scheduler_until_stopped,
+    run_worker_until_stopped,
+};
 use crate::domain::{
     AppBaseUrl,
     SubscriberEmail,
+    UnsubscribeLinks,
+    WebhookCredentials,
+};
+use crate::email_client::{
+    CircuitBreaker,
+    EmailClient,
+    EmailProvider,
+    EmailSender,
+    FailoverSender,
+    OutboxClient,
+    SmtpClient,
 };
-use crate::email_client::EmailClient;
 use crate::routes::*;
+use crate::templates::EmailTemplates;
 
 pub struct NewsletterApp {
     pub server: Result<Server, std::io::Error>,
@@ -31,12 +50,60 @@ pub struct NewsletterApp {
 
 impl NewsletterApp {
     pub async fn from(configuration: Settings) -> Result<NewsletterApp, std::io::Error> {
+        let email_sender = NewsletterApp::email_sender(
+            configuration.email_client.clone(),
+            &configuration.application.unsubscribe_secret,
+        );
+        NewsletterApp::with_email_sender(configuration, email_sender).await
+    }
+
+    /// Build the app on top of `email_sender`, instead of the backend selected
+    /// by `configuration.email_client`.
+    pub async fn with_email_sender(
+        configuration: Settings,
+        email_sender: Arc<dyn EmailSender>,
+    ) -> Result<NewsletterApp, std::io::Error> {
         let tcp_listener = TcpListener::bind(configuration.application.binding_address())?;
         let port = tcp_listener.local_addr().unwrap().port();
         let postgres_pool =
             web::Data::new(NewsletterApp::postgres_pool(configuration.database).await);
-        let email_client = web::Data::new(NewsletterApp::email_client(configuration.email_client));
+        let unsubscribe_links = UnsubscribeLinks::new(
+            configuration.application.base_url.clone(),
+            configuration.application.unsubscribe_secret,
+            configuration.application.unsubscribe_mailbox,
+        );
+        let email_templates = web::Data::new(
+            EmailTemplates::load(Path::new(&configuration.application.templates_directory))
+                .unwrap_or_else(|e| panic!("Error: {:?} loading email templates", e)),
+        );
         let app_base_url = web::Data::new(AppBaseUrl(configuration.application.base_url));
+        let webhook_credentials = web::Data::new(WebhookCredentials {
+            username: configuration.email_client.webhook.username,
+            password: configuration.email_client.webhook.password,
+        });
+
+        // the delivery workers drain the queue filled by `newsletters` in the
+        // background
+        let poll_interval =
+            Duration::from_millis(configuration.application.delivery_poll_interval_millis);
+        for _ in 0..configuration.application.delivery_workers {
+            actix_web::rt::spawn(run_worker_until_stopped(
+                postgres_pool.get_ref().clone(),
+                email_sender.clone(),
+                unsubscribe_links.clone(),
+                poll_interval,
+            ));
+        }
+        actix_web::rt::spawn(run_scheduler_until_stopped(
+            postgres_pool.get_ref().clone(),
+            Duration::from_millis(configuration.application.scheduler_poll_interval_millis),
+        ));
+
+        let email_sender = web::Data::from(email_sender);
+        let unsubscribe_links = web::Data::new(unsubscribe_links);
+        // only the routes receiving articles accept payloads large enough for
+        // their attachments
+        let article_json_config = web::JsonConfig::default().limit(MAX_ARTICLE_SIZE);
 
         // HttpServer handles all transport level concerns
         let server = HttpServer::new(move || {
@@ -51,15 +118,67 @@ impl NewsletterApp {
                 // the check method verifies the guards conditions are met
                 // and eventually call the handler
                 .route("/health_check", web::get().to(health_check))
+                .route("/metrics", web::get().to(metrics))
                 // we need to clone the input connection  because the current closure will be called
                 // multiple times (in fact it is of type Fn not FnOnce) and the input connection
                 // would not be available anymore at the next call otherwise.
                 .route("/subscriptions", web::post().to(subscribe))
                 .route("/subscriptions/confirm", web::get().to(confirm))
-                .route("/newsletters", web::post().to(newsletters))
+                .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+                .route(
+                    "/subscriptions/unsubscribe",
+                    web::get().to(unsubscribe_form),
+                )
+                .route("/email_events", web::post().to(email_events))
+                .service(
+                    web::resource("/newsletters")
+                        .app_data(article_json_config.clone())
+                        .route(web::post().to(newsletters))
+                        .route(web::get().to(newsletter_issues)),
+                )
+                .service(
+                    web::resource("/newsletters/drafts")
+                        .app_data(article_json_config.clone())
+                        .route(web::post().to(create_draft)),
+                )
+                .service(
+                    web::resource("/newsletters/drafts/{issue_id}")
+                        .app_data(article_json_config.clone())
+                        .route(web::put().to(update_draft)),
+                )
+                .route(
+                    "/newsletters/drafts/{issue_id}/test",
+                    web::post().to(test_draft),
+                )
+                .route(
+                    "/newsletters/drafts/{issue_id}/publish",
+                    web::post().to(publish_draft),
+                )
+                .route(
+                    "/newsletters/scheduled",
+                    web::get().to(scheduled_newsletters),
+                )
+                .route(
+                    "/newsletters/scheduled/{issue_id}",
+                    web::put().to(reschedule_newsletter),
+                )
+                .route(
+                    "/newsletters/scheduled/{issue_id}",
+                    web::delete().to(cancel_newsletter),
+                )
+                // registered after `/newsletters/drafts` and `/newsletters/scheduled` so that it
+                // does not shadow them
+                .route("/newsletters/{issue_id}", web::get().to(newsletter_issue))
+                .route(
+                    "/newsletters/{issue_id}/report",
+                    web::get().to(newsletter_report),
+                )
                 .app_data(postgres_pool.clone())
-                .app_data(email_client.clone())
+                .app_data(email_sender.clone())
                 .app_data(app_base_url.clone())
+                .app_data(webhook_credentials.clone())
+                .app_data(unsubscribe_links.clone())
+                .app_data(email_templates.clone())
         })
         .backlog(configuration.application.max_pending_connections)
         .listen(tcp_listener)
@@ -83,6 +202,55 @@ impl NewsletterApp {
             })
     }
 
+    /// The provider of `client_config`, then its fallbacks.
+    ///
+    /// `secret` keys the hashes identifying the recipients in the logs.
+    fn email_sender(client_config: EmailClientSettings, secret: &str) -> Arc<dyn EmailSender> {
+        let mut providers = vec![EmailProvider {
+            name: client_config.provider_name().to_string(),
+            sender: NewsletterApp::provider_sender(
+                client_config.provider_name(),
+                client_config.clone(),
+                secret,
+            ),
+        }];
+        for fallback in &client_config.fallbacks {
+            providers.push(EmailProvider {
+                name: fallback.name.clone(),
+                sender: NewsletterApp::provider_sender(
+                    &fallback.name,
+                    client_config.with_provider(fallback),
+                    secret,
+                ),
+            });
+        }
+        Arc::new(FailoverSender::new(providers))
+    }
+
+    fn provider_sender(
+        name: &str,
+        client_config: EmailClientSettings,
+        secret: &str,
+    ) -> Arc<dyn EmailSender> {
+        let circuit_breaker = client_config.circuit_breaker.clone();
+        let email_sender: Arc<dyn EmailSender> = match client_config.backend {
+            EmailBackend::Mailjet => Arc::new(
+                NewsletterApp::email_client(client_config)
+                    .with_provider(name.to_string())
+                    .with_recipient_hash_key(secret.as_bytes().to_vec()),
+            ),
+            EmailBackend::Smtp => Arc::new(NewsletterApp::smtp_client(client_config)),
+            EmailBackend::Outbox => Arc::new(NewsletterApp::outbox_client(client_config)),
+        };
+        match circuit_breaker {
+            Some(circuit_breaker) => Arc::new(CircuitBreaker::new(
+                email_sender,
+                circuit_breaker.circuit_breaker_policy(),
+            )),
+            None => email_sender,
+        }
+    }
+
     fn email_client(client_config: EmailClientSettings) -> EmailClient {
         let base_url = Url::parse(&client_config.base_url).unwrap_or_else(|e| {
             panic!("Error: {} parsing base url: {}", e, client_config.base_url)
@@ -93,12 +261,85 @@ impl NewsletterApp {
             .try_into()
             .unwrap_or_else(|e| panic!("Error: {} parsing sender email from config", e));
 
-        EmailClient::new(
+        let email_client = EmailClient::new(
             base_url,
             sender_email,
             client_config.token,
             client_config.timeout_secs,
         )
         .unwrap_or_else(|e| panic!("Error: {} creating EmailClient", e))
+        .with_sender_name(client_config.sender_name)
+        .with_retry_policy(client_config.retry.retry_policy())
+        .with_max_batch_size(client_config.max_batch_size)
+        .with_max_concurrent_requests(client_config.max_concurrent_requests);
+        let email_client = match &client_config.rate_limit {
+            Some(rate_limit) => email_client.with_rate_limit(rate_limit.rate_limit()),
+            None => email_client,
+        };
+        match NewsletterApp::reply_to(client_config.reply_to) {
+            Some(reply_to) => email_client.with_reply_to(reply_to),
+            None => email_client,
+        }
+    }
+
+    fn smtp_client(client_config: EmailClientSettings) -> SmtpClient {
+        let smtp_settings = client_config.smtp.unwrap_or_else(|| {
+            panic!("Error: the smtp backend requires the email_client.smtp settings")
+        });
+
+        let sender_email: SubscriberEmail = client_config
+            .sender_email
+            .try_into()
+            .unwrap_or_else(|e| panic!("Error: {} parsing sender email from config", e));
+
+        let smtp_client = SmtpClient::new(
+            smtp_settings.smtp_server(),
+            sender_email,
+            client_config.timeout_secs,
+            client_config.max_concurrent_requests,
+        )
+        .unwrap_or_else(|e| panic!("Error: {} creating SmtpClient", e))
+        .with_sender_name(client_config.sender_name)
+        .with_retry_policy(client_config.retry.retry_policy());
+        let smtp_client = match &smtp_settings.dkim {
+            Some(dkim) => smtp_client.with_dkim_signer(
+                dkim.dkim_signer()
+                    .unwrap_or_else(|e| panic!("Error: {} creating DkimSigner", e)),
+            ),
+            None => smtp_client,
+        };
+        match NewsletterApp::reply_to(client_config.reply_to) {
+            Some(reply_to) => smtp_client
+                .with_reply_to(reply_to)
+                .unwrap_or_else(|e| panic!("Error: {} creating SmtpClient", e)),
+            None => smtp_client,
+        }
+    }
+
+    fn outbox_client(client_config: EmailClientSettings) -> OutboxClient {
+        let outbox_settings = client_config.outbox.unwrap_or_else(|| {
+            panic!("Error: the outbox backend requires the email_client.outbox settings")
+        });
+
+        let sender_email: SubscriberEmail = client_config
+            .sender_email
+            .try_into()
+            .unwrap_or_else(|e| panic!("Error: {} parsing sender email from config", e));
+
+        let outbox_client = OutboxClient::new(outbox_settings.directory.into(), sender_email)
+            .unwrap_or_else(|e| panic!("Error: {} creating OutboxClient", e))
+            .with_sender_name(client_config.sender_name);
+        match NewsletterApp::reply_to(client_config.reply_to) {
+            Some(reply_to) => outbox_client.with_reply_to(reply_to),
+            None => outbox_client,
+        }
+    }
+
+    fn reply_to(reply_to: Option<String>) -> Option<SubscriberEmail> {
+        reply_to.map(|reply_to| {
+            reply_to
+                .try_into()
+                .unwrap_or_else(|e| panic!("Error: {} parsing reply-to email from config", e))
+        })
     }
 }
@@ -0,0 +1,11 @@
+pub use attachments::*;
+pub use queue::*;
+pub use report::*;
+pub use scheduler::*;
+pub use worker::*;
+
+mod attachments;
+mod queue;
+mod report;
+mod scheduler;
+mod worker;
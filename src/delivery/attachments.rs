@@ -0,0 +1,92 @@
+use std::convert::TryFrom;
+
+use anyhow::Context;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::domain::{
+    EmailAttachment,
+    EmailAttachments,
+};
+
+struct StoredAttachment {
+    filename: String,
+    content_type: String,
+    content_id: Option<String>,
+    content: Vec<u8>,
+}
+
+/// Replace the attachments of `issue_id` with `attachments`, keeping their
+/// order.
+#[tracing::instrument(
+    name = "Storing issue attachments",
+    skip(attachments, postgres_connection)
+)]
+pub async fn store_issue_attachments(
+    issue_id: &Uuid,
+    attachments: &EmailAttachments,
+    postgres_connection: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issue_attachments
+        WHERE issue_id = $1
+        "#,
+        issue_id
+    )
+    .execute(&mut *postgres_connection)
+    .await?;
+    for (position, attachment) in attachments.as_ref().iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue_attachments
+                (issue_id, position, filename, content_type, content_id, content)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            issue_id,
+            position as i32,
+            attachment.filename(),
+            attachment.content_type(),
+            attachment.content_id(),
+            attachment.content()
+        )
+        .execute(&mut *postgres_connection)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Retrieve the attachments of `issue_id`, in the order they were stored.
+#[tracing::instrument(name = "Retrieving issue attachments", skip(postgres_connection))]
+pub async fn get_issue_attachments(
+    issue_id: &Uuid,
+    postgres_connection: &mut PgConnection,
+) -> Result<EmailAttachments, anyhow::Error> {
+    let stored_attachments = sqlx::query_as!(
+        StoredAttachment,
+        r#"
+        SELECT filename, content_type, content_id, content
+        FROM newsletter_issue_attachments
+        WHERE issue_id = $1
+        ORDER BY position
+        "#,
+        issue_id
+    )
+    .fetch_all(postgres_connection)
+    .await?;
+    let attachments = stored_attachments
+        .into_iter()
+        .map(|attachment| {
+            EmailAttachment::parse(
+                attachment.filename,
+                attachment.content_type,
+                attachment.content,
+                attachment.content_id,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(EmailAttachments::try_from)
+        .map_err(anyhow::Error::msg)
+        .context("Invalid attachment retrieved from db")?;
+    Ok(attachments)
+}
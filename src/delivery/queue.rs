@@ -0,0 +1,121 @@
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use sqlx::{
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    email: String,
+}
+
+/// Enqueue one delivery task for every confirmed subscriber.
+///
+/// Subscribers whose stored email is no longer valid get a `skipped` task, so
+/// they show up in the [`DeliveryReport`](crate::delivery::DeliveryReport) of
+/// the issue. The tasks are only visible to the workers once
+/// `postgres_transaction` is committed.
+#[tracing::instrument(name = "Enqueuing delivery tasks", skip(postgres_transaction))]
+pub async fn enqueue_delivery_tasks(
+    issue_id: &Uuid,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    let confirmed_subscribers = get_confirmed_subscribers(postgres_transaction).await?;
+    for subscriber in confirmed_subscribers {
+        match SubscriberEmail::try_from(subscriber.email.clone()) {
+            Ok(subscriber_email) => {
+                insert_delivery_task(
+                    issue_id,
+                    &subscriber.id,
+                    &subscriber_email,
+                    postgres_transaction,
+                )
+                .await?;
+            }
+            Err(e) => {
+                tracing::warn!("Invalid email retrieved from db: {}", e);
+                insert_skipped_task(
+                    issue_id,
+                    &subscriber.id,
+                    &subscriber.email,
+                    &e,
+                    postgres_transaction,
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Retrieving confirmed subscribers", skip(postgres_transaction))]
+async fn get_confirmed_subscribers(
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<ConfirmedSubscriber>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"
+        SELECT id, email
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+    )
+    .fetch_all(postgres_transaction)
+    .await?;
+    Ok(rows)
+}
+
+async fn insert_delivery_task(
+    issue_id: &Uuid,
+    subscriber_id: &Uuid,
+    subscriber_email: &SubscriberEmail,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_tasks
+            (id, issue_id, subscriber_id, subscriber_email, status, created_at)
+        VALUES ($1, $2, $3, $4, 'pending', $5)
+        "#,
+        Uuid::new_v4(),
+        issue_id,
+        subscriber_id,
+        subscriber_email.as_ref(),
+        Utc::now()
+    )
+    .execute(postgres_transaction)
+    .await?;
+    Ok(())
+}
+
+async fn insert_skipped_task(
+    issue_id: &Uuid,
+    subscriber_id: &Uuid,
+    subscriber_email: &str,
+    reason: &str,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_tasks
+            (id, issue_id, subscriber_id, subscriber_email, status, last_error, created_at,
+             completed_at)
+        VALUES ($1, $2, $3, $4, 'skipped', $5, $6, $6)
+        "#,
+        Uuid::new_v4(),
+        issue_id,
+        subscriber_id,
+        subscriber_email,
+        reason,
+        now
+    )
+    .execute(postgres_transaction)
+    .await?;
+    Ok(())
+}
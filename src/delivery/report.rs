@@ -0,0 +1,76 @@
+use serde::Serialize;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// The delivery state of every recipient of a newsletter issue.
+#[derive(Debug, Default, Serialize)]
+pub struct DeliveryReport {
+    pub issue_id: Uuid,
+    /// Recipients with a valid email: `sent + failed + pending`.
+    pub attempted: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub pending: i64,
+    pub skipped_invalid: i64,
+    pub failures: Vec<DeliveryFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeliveryFailure {
+    pub subscriber_email: String,
+    pub reason: String,
+}
+
+struct StatusCount {
+    status: String,
+    count: i64,
+}
+
+/// Build the [`DeliveryReport`] of `issue_id` from its delivery tasks.
+#[tracing::instrument(name = "Building delivery report", skip(postgres_connection))]
+pub async fn get_delivery_report(
+    issue_id: &Uuid,
+    postgres_connection: &mut PgConnection,
+) -> Result<DeliveryReport, sqlx::Error> {
+    let status_counts = sqlx::query_as!(
+        StatusCount,
+        r#"
+        SELECT status, count(*) as "count!"
+        FROM issue_delivery_tasks
+        WHERE issue_id = $1
+        GROUP BY status
+        "#,
+        issue_id
+    )
+    .fetch_all(&mut *postgres_connection)
+    .await?;
+    let failures = sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT subscriber_email, coalesce(last_error, 'unknown error') as "reason!"
+        FROM issue_delivery_tasks
+        WHERE issue_id = $1 AND status IN ('failed', 'skipped')
+        ORDER BY completed_at
+        "#,
+        issue_id
+    )
+    .fetch_all(&mut *postgres_connection)
+    .await?;
+
+    let mut report = DeliveryReport {
+        issue_id: *issue_id,
+        failures,
+        ..Default::default()
+    };
+    for StatusCount { status, count } in status_counts {
+        match status.as_str() {
+            "sent" => report.sent = count,
+            "failed" => report.failed = count,
+            "pending" => report.pending = count,
+            "skipped" => report.skipped_invalid = count,
+            other => tracing::warn!("Unexpected delivery task status: {}", other),
+        }
+    }
+    report.attempted = report.sent + report.failed + report.pending;
+    Ok(report)
+}
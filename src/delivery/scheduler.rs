@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+use crate::delivery::enqueue_delivery_tasks;
+
+pub enum SchedulingOutcome {
+    IssuePublished,
+    NothingDue,
+}
+
+/// Publish scheduled issues forever, as soon as their time comes.
+///
+/// When no issue is due, or the database cannot be reached, the scheduler
+/// sleeps for `poll_interval` before trying again.
+pub async fn run_scheduler_until_stopped(postgres_pool: PgPool, poll_interval: Duration) {
+    loop {
+        match try_publish_due_issue(&postgres_pool).await {
+            Ok(SchedulingOutcome::IssuePublished) => {}
+            Ok(SchedulingOutcome::NothingDue) => tokio::time::sleep(poll_interval).await,
+            Err(e) => {
+                tracing::warn!("Error publishing scheduled issue: {:?}", e);
+                tokio::time::sleep(poll_interval).await
+            }
+        }
+    }
+}
+
+/// Move the oldest due scheduled issue into the delivery queue.
+///
+/// The issue row stays locked until its delivery tasks are committed, so an
+/// issue cancelled or rescheduled concurrently is either published in full
+/// or not at all.
+#[tracing::instrument(
+    name = "Publishing scheduled issue",
+    skip(postgres_pool),
+    fields(issue_id=tracing::field::Empty)
+)]
+pub async fn try_publish_due_issue(
+    postgres_pool: &PgPool,
+) -> Result<SchedulingOutcome, anyhow::Error> {
+    let mut transaction = postgres_pool
+        .begin()
+        .await
+        .context("Failed to start SQL transaction to publish a scheduled issue")?;
+    let issue_id = match dequeue_due_issue(&mut transaction)
+        .await
+        .context("Failed to retrieve due scheduled issue")?
+    {
+        Some(issue_id) => issue_id,
+        None => return Ok(SchedulingOutcome::NothingDue),
+    };
+    tracing::Span::current().record("issue_id", &tracing::field::display(issue_id));
+
+    mark_issue_as_published(&issue_id, &mut transaction)
+        .await
+        .context("Failed to mark scheduled issue as published")?;
+    enqueue_delivery_tasks(&issue_id, &mut transaction)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a scheduled issue")?;
+    Ok(SchedulingOutcome::IssuePublished)
+}
+
+async fn dequeue_due_issue(
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND scheduled_for <= $1
+        ORDER BY scheduled_for
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+        Utc::now()
+    )
+    .fetch_optional(postgres_transaction)
+    .await?;
+    Ok(issue.map(|issue| issue.id))
+}
+
+async fn mark_issue_as_published(
+    issue_id: &Uuid,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published', published_at = $2
+        WHERE id = $1
+        "#,
+        issue_id,
+        Utc::now()
+    )
+    .execute(postgres_transaction)
+    .await?;
+    Ok(())
+}
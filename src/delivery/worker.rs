@@ -0,0 +1,374 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+use crate::delivery::get_issue_attachments;
+use crate::domain::{
+    SubscriberEmail,
+    UnsubscribeLinks,
+};
+use crate::email_client::{
+    CircuitState,
+    Email,
+    EmailSender,
+    NotAttempted,
+    SentEmail,
+};
+use crate::templates::EmailTemplate;
+
+pub enum ExecutionOutcome {
+    TasksCompleted,
+    EmptyQueue,
+    /// The circuit breaker of the [`EmailSender`] is open, or none of the
+    /// emails dequeued were [attempted](NotAttempted): the tasks are left
+    /// pending.
+    ProviderUnavailable,
+}
+
+/// Drain the delivery queue forever.
+///
+/// When the queue is empty, the email provider is unavailable, or the
+/// database cannot be reached, the worker sleeps for `poll_interval` before
+/// trying again.
+pub async fn run_worker_until_stopped(
+    postgres_pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    unsubscribe_links: UnsubscribeLinks,
+    poll_interval: Duration,
+) {
+    loop {
+        match try_execute_tasks(&postgres_pool, email_client.as_ref(), &unsubscribe_links).await {
+            Ok(ExecutionOutcome::TasksCompleted) => {}
+            Ok(ExecutionOutcome::EmptyQueue | ExecutionOutcome::ProviderUnavailable) => {
+                tokio::time::sleep(poll_interval).await
+            }
+            Err(e) => {
+                tracing::warn!("Error executing delivery tasks: {:?}", e);
+                tokio::time::sleep(poll_interval).await
+            }
+        }
+    }
+}
+
+struct DeliveryTask {
+    id: Uuid,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    subscriber_name: String,
+    /// The status of the subscription when the task is dequeued, which may
+    /// have changed since the task was enqueued.
+    subscriber_status: String,
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Dequeue enough pending tasks of the same issue to fill every concurrent
+/// request of the [`EmailSender`], send the issue to their subscribers and
+/// record the result of each task.
+///
+/// The content of the issue is rendered with the fields of each subscriber,
+/// and every email carries the one-click unsubscribe link of its subscriber.
+///
+/// The task rows stay locked until the results are committed, so concurrent
+/// workers never deliver the same task twice and a crash leaves the tasks
+/// pending.
+#[tracing::instrument(
+    name = "Executing delivery tasks",
+    skip(postgres_pool, email_client, unsubscribe_links),
+    fields(
+        issue_id=tracing::field::Empty,
+        tasks=tracing::field::Empty,
+    )
+)]
+pub async fn try_execute_tasks(
+    postgres_pool: &PgPool,
+    email_client: &dyn EmailSender,
+    unsubscribe_links: &UnsubscribeLinks,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    // the deliveries are deferred rather than failed while the circuit is open;
+    // once it is half-open, the batch of a single worker probes the provider and
+    // the emails of the others are not attempted, leaving their tasks pending
+    if email_client.circuit_state() == Some(CircuitState::Open) {
+        return Ok(ExecutionOutcome::ProviderUnavailable);
+    }
+    let mut transaction = postgres_pool
+        .begin()
+        .await
+        .context("Failed to start SQL transaction to dequeue delivery tasks")?;
+    let max_tasks = email_client.max_batch_size() * email_client.max_concurrent_requests();
+    let tasks = dequeue_tasks(max_tasks, &mut transaction)
+        .await
+        .context("Failed to dequeue delivery tasks")?;
+    let issue_id = match tasks.first() {
+        Some(task) => task.issue_id,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    let span = tracing::Span::current();
+    span.record("issue_id", &tracing::field::display(issue_id));
+    span.record("tasks", &tasks.len());
+
+    let issue = get_issue(&issue_id, &mut transaction)
+        .await
+        .context("Failed to retrieve newsletter issue")?;
+    let attachments = get_issue_attachments(&issue_id, &mut transaction)
+        .await
+        .context("Failed to retrieve newsletter issue attachments")?;
+    let template = EmailTemplate::issue(&issue.html_content, &issue.text_content);
+    let mut contents = Vec::with_capacity(tasks.len());
+    let mut email_tasks = Vec::with_capacity(tasks.len());
+    let mut task_results = Vec::with_capacity(tasks.len());
+    let mut skipped_tasks = Vec::new();
+    for task in &tasks {
+        // the subscriber bounced, complained or unsubscribed since the issue
+        // was published
+        if task.subscriber_status != "confirmed" {
+            skipped_tasks.push((
+                task.id,
+                format!("The subscriber is {}", task.subscriber_status),
+            ));
+            continue;
+        }
+        let recipient = SubscriberEmail::try_from(task.subscriber_email.clone());
+        match recipient {
+            Ok(recipient) => {
+                let unsubscribe_link = unsubscribe_links.url(&task.subscriber_id);
+                contents.push(template.render(&[
+                    ("name", &task.subscriber_name),
+                    ("unsubscribe_link", &unsubscribe_link),
+                ]));
+                email_tasks.push((task, recipient));
+            }
+            Err(e) => task_results.push((task.id, Err(e))),
+        }
+    }
+    let mut emails = Vec::with_capacity(email_tasks.len());
+    let mut email_task_ids = Vec::with_capacity(email_tasks.len());
+    for ((task, recipient), content) in email_tasks.into_iter().zip(&contents) {
+        emails.push(Email {
+            recipient_name: Some(&task.subscriber_name),
+            attachments: attachments.as_ref(),
+            custom_id: Some(custom_id(&issue_id, &task.subscriber_id)),
+            headers: list_unsubscribe_headers(unsubscribe_links, &task.subscriber_id),
+            ..Email::new(recipient, &issue.title, &content.html, &content.text)
+        });
+        email_task_ids.push(task.id);
+    }
+    let delivery_results = match emails.is_empty() {
+        true => Vec::new(),
+        false => email_client.send_emails(&emails).await,
+    };
+    let mut not_attempted = Vec::new();
+    for (task_id, result) in email_task_ids.into_iter().zip(delivery_results) {
+        match result {
+            // the task is unlocked by the commit, and dequeued again later on
+            Err(e) => match NotAttempted::find(&e) {
+                Some(reason) => not_attempted.push(reason),
+                None => task_results.push((task_id, Err(format!("{:#}", e)))),
+            },
+            Ok(sent_email) => task_results.push((task_id, Ok(sent_email))),
+        }
+    }
+    if let Some(reason) = not_attempted.first() {
+        tracing::warn!(
+            "Deferring the delivery of {} task(s): {}",
+            not_attempted.len(),
+            reason
+        );
+    }
+
+    for (task_id, result) in task_results {
+        match result {
+            Ok(sent_email) => mark_task_as_sent(&task_id, &sent_email, &mut transaction)
+                .await
+                .context("Failed to mark delivery task as sent")?,
+            Err(e) => {
+                tracing::warn!("Error delivering newsletter issue: {}", e);
+                mark_task_as_failed(&task_id, &e, &mut transaction)
+                    .await
+                    .context("Failed to mark delivery task as failed")?
+            }
+        }
+    }
+    for (task_id, reason) in skipped_tasks {
+        mark_task_as_skipped(&task_id, &reason, &mut transaction)
+            .await
+            .context("Failed to mark delivery task as skipped")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to complete delivery tasks")?;
+    if not_attempted.len() == tasks.len() {
+        return Ok(ExecutionOutcome::ProviderUnavailable);
+    }
+    Ok(ExecutionOutcome::TasksCompleted)
+}
+
+/// Correlate the delivery events reported by the provider with the issue
+/// and subscriber of the task.
+fn custom_id(issue_id: &Uuid, subscriber_id: &Uuid) -> String {
+    format!("{}:{}", issue_id, subscriber_id)
+}
+
+/// The `List-Unsubscribe` and `List-Unsubscribe-Post` headers of RFC 8058,
+/// letting mail clients unsubscribe with a single click.
+fn list_unsubscribe_headers(
+    unsubscribe_links: &UnsubscribeLinks,
+    subscriber_id: &Uuid,
+) -> Vec<(&'static str, String)> {
+    let mut list_unsubscribe = format!("<{}>", unsubscribe_links.url(subscriber_id));
+    if let Some(mailto) = unsubscribe_links.mailto(subscriber_id) {
+        list_unsubscribe.push_str(&format!(", <{}>", mailto));
+    }
+    vec![
+        ("List-Unsubscribe", list_unsubscribe),
+        (
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click".to_string(),
+        ),
+    ]
+}
+
+/// Lock the oldest pending task and up to `max_tasks - 1` other pending tasks
+/// of the same issue.
+async fn dequeue_tasks(
+    max_tasks: usize,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<DeliveryTask>, sqlx::Error> {
+    let first_task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT t.id, t.issue_id, t.subscriber_id, t.subscriber_email, s.name as subscriber_name,
+            s.status as subscriber_status
+        FROM issue_delivery_tasks t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.status = 'pending'
+        ORDER BY t.created_at
+        FOR UPDATE OF t
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *postgres_transaction)
+    .await?;
+    let first_task = match first_task {
+        Some(task) => task,
+        None => return Ok(Vec::new()),
+    };
+    let mut tasks = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT t.id, t.issue_id, t.subscriber_id, t.subscriber_email, s.name as subscriber_name,
+            s.status as subscriber_status
+        FROM issue_delivery_tasks t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.status = 'pending' AND t.issue_id = $1 AND t.id <> $2
+        ORDER BY t.created_at
+        FOR UPDATE OF t
+        SKIP LOCKED
+        LIMIT $3
+        "#,
+        first_task.issue_id,
+        first_task.id,
+        max_tasks.saturating_sub(1) as i64
+    )
+    .fetch_all(&mut *postgres_transaction)
+    .await?;
+    tasks.insert(0, first_task);
+    Ok(tasks)
+}
+
+async fn get_issue(
+    issue_id: &Uuid,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(postgres_transaction)
+    .await
+}
+
+/// The provider message id is kept to match the delivery events reported
+/// later on to the task, along with the provider that carried it.
+async fn mark_task_as_sent(
+    task_id: &Uuid,
+    sent_email: &SentEmail,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_tasks
+        SET status = 'sent', attempts = attempts + 1, last_error = NULL, completed_at = $2,
+            provider_message_id = $3, provider = $4
+        WHERE id = $1
+        "#,
+        task_id,
+        Utc::now(),
+        sent_email.message_id,
+        sent_email.provider
+    )
+    .execute(postgres_transaction)
+    .await?;
+    Ok(())
+}
+
+async fn mark_task_as_failed(
+    task_id: &Uuid,
+    error: &str,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_tasks
+        SET status = 'failed', attempts = attempts + 1, last_error = $2, completed_at = $3
+        WHERE id = $1
+        "#,
+        task_id,
+        error,
+        Utc::now()
+    )
+    .execute(postgres_transaction)
+    .await?;
+    Ok(())
+}
+
+async fn mark_task_as_skipped(
+    task_id: &Uuid,
+    reason: &str,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_tasks
+        SET status = 'skipped', last_error = $2, completed_at = $3
+        WHERE id = $1
+        "#,
+        task_id,
+        reason,
+        Utc::now()
+    )
+    .execute(postgres_transaction)
+    .await?;
+    Ok(())
+}
@@ -1,9 +1,21 @@
 pub use app_base_url::AppBaseUrl;
+pub use email_attachment::{
+    EmailAttachment,
+    EmailAttachments,
+    MAX_ATTACHMENTS_SIZE,
+};
+pub use idempotency_key::IdempotencyKey;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use unsubscribe_links::UnsubscribeLinks;
+pub use webhook_credentials::WebhookCredentials;
 
 mod app_base_url;
+mod email_attachment;
+mod idempotency_key;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod unsubscribe_links;
+mod webhook_credentials;
@@ -0,0 +1,254 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+/// The largest attachment accepted, once decoded.
+pub const MAX_ATTACHMENT_SIZE: usize = 5 * 1024 * 1024;
+/// The largest size of all the attachments of an email, once decoded, well
+/// below the message size limit of the providers.
+pub const MAX_ATTACHMENTS_SIZE: usize = 10 * 1024 * 1024;
+const MAX_FILENAME_LENGTH: usize = 255;
+const MAX_CONTENT_ID_LENGTH: usize = 100;
+
+const ATTACHMENT_CONTENT_TYPES: [&str; 6] = [
+    "application/pdf",
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "text/csv",
+    "text/plain",
+];
+const INLINE_CONTENT_TYPES: [&str; 3] = ["image/gif", "image/jpeg", "image/png"];
+
+/// A file sent along with an email.
+///
+/// An attachment with a content id is an inline image, displayed by the HTML
+/// part wherever it references `cid:<content_id>`.
+#[derive(Clone, Debug)]
+pub struct EmailAttachment {
+    filename: String,
+    content_type: String,
+    content: Vec<u8>,
+    content_id: Option<String>,
+}
+
+/// The attachments of an email, with unique content ids and a bounded total
+/// size.
+#[derive(Clone, Debug, Default)]
+pub struct EmailAttachments(Vec<EmailAttachment>);
+
+impl EmailAttachment {
+    pub fn parse(
+        filename: String,
+        content_type: String,
+        content: Vec<u8>,
+        content_id: Option<String>,
+    ) -> Result<Self, String> {
+        if filename.trim().is_empty()
+            || filename.len() > MAX_FILENAME_LENGTH
+            || filename
+                .chars()
+                .any(|c| c.is_control() || c == '/' || c == '\\')
+        {
+            return Err(format!("Invalid attachment filename: {:?}", filename));
+        }
+        let content_type = content_type.trim().to_lowercase();
+        let allowed_content_types: &[&str] = match content_id {
+            Some(_) => &INLINE_CONTENT_TYPES,
+            None => &ATTACHMENT_CONTENT_TYPES,
+        };
+        if !allowed_content_types.contains(&content_type.as_str()) {
+            return Err(format!(
+                "Unsupported content type: {} for attachment: {}, expected one of: {}",
+                content_type,
+                filename,
+                allowed_content_types.join(", ")
+            ));
+        }
+        if content.is_empty() || content.len() > MAX_ATTACHMENT_SIZE {
+            return Err(format!(
+                "The attachment: {} must be between 1 and {} bytes long",
+                filename, MAX_ATTACHMENT_SIZE
+            ));
+        }
+        if let Some(content_id) = &content_id {
+            if content_id.is_empty()
+                || content_id.len() > MAX_CONTENT_ID_LENGTH
+                || !content_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.@".contains(c))
+            {
+                return Err(format!("Invalid attachment content id: {:?}", content_id));
+            }
+        }
+        Ok(Self {
+            filename,
+            content_type,
+            content,
+            content_id,
+        })
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    pub fn content_id(&self) -> Option<&str> {
+        self.content_id.as_deref()
+    }
+
+    pub fn is_inline(&self) -> bool {
+        self.content_id.is_some()
+    }
+}
+
+impl TryFrom<Vec<EmailAttachment>> for EmailAttachments {
+    type Error = String;
+
+    fn try_from(attachments: Vec<EmailAttachment>) -> Result<Self, Self::Error> {
+        let total_size: usize = attachments
+            .iter()
+            .map(|attachment| attachment.content.len())
+            .sum();
+        if total_size > MAX_ATTACHMENTS_SIZE {
+            return Err(format!(
+                "The attachments must not exceed {} bytes in total",
+                MAX_ATTACHMENTS_SIZE
+            ));
+        }
+        let mut content_ids = HashSet::new();
+        if let Some(content_id) = attachments
+            .iter()
+            .filter_map(EmailAttachment::content_id)
+            .find(|content_id| !content_ids.insert(*content_id))
+        {
+            return Err(format!("Duplicated attachment content id: {}", content_id));
+        }
+        Ok(Self(attachments))
+    }
+}
+
+impl AsRef<[EmailAttachment]> for EmailAttachments {
+    fn as_ref(&self) -> &[EmailAttachment] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use claim::{
+        assert_err,
+        assert_ok,
+    };
+
+    use super::*;
+
+    fn attachment(content_type: &str, size: usize, content_id: Option<&str>) -> EmailAttachment {
+        EmailAttachment::parse(
+            "attachment".to_string(),
+            content_type.to_string(),
+            vec![0; size],
+            content_id.map(str::to_string),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn valid_attachments_are_parsed_successfully() {
+        assert_ok!(EmailAttachment::parse(
+            "report.pdf".to_string(),
+            "Application/PDF".to_string(),
+            vec![0; MAX_ATTACHMENT_SIZE],
+            None,
+        ));
+        assert_ok!(EmailAttachment::parse(
+            "logo.png".to_string(),
+            "image/png".to_string(),
+            vec![0; 10],
+            Some("logo@newsletter".to_string()),
+        ));
+    }
+
+    #[test]
+    fn invalid_filename_is_rejected() {
+        for filename in ["", " ", "../report.pdf", "report\n.pdf"].iter() {
+            assert_err!(EmailAttachment::parse(
+                filename.to_string(),
+                "application/pdf".to_string(),
+                vec![0; 10],
+                None,
+            ));
+        }
+    }
+
+    #[test]
+    fn unsupported_content_type_is_rejected() {
+        assert_err!(EmailAttachment::parse(
+            "script.sh".to_string(),
+            "application/x-sh".to_string(),
+            vec![0; 10],
+            None,
+        ));
+        // only images can be displayed inline
+        assert_err!(EmailAttachment::parse(
+            "report.pdf".to_string(),
+            "application/pdf".to_string(),
+            vec![0; 10],
+            Some("report".to_string()),
+        ));
+    }
+
+    #[test]
+    fn empty_or_too_large_content_is_rejected() {
+        for size in [0, MAX_ATTACHMENT_SIZE + 1].iter() {
+            assert_err!(EmailAttachment::parse(
+                "report.pdf".to_string(),
+                "application/pdf".to_string(),
+                vec![0; *size],
+                None,
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_content_id_is_rejected() {
+        for content_id in ["", "<logo>", "logo image"].iter() {
+            assert_err!(EmailAttachment::parse(
+                "logo.png".to_string(),
+                "image/png".to_string(),
+                vec![0; 10],
+                Some(content_id.to_string()),
+            ));
+        }
+    }
+
+    #[test]
+    fn attachments_exceeding_the_total_size_are_rejected() {
+        let attachments = (0..3)
+            .map(|_| attachment("application/pdf", MAX_ATTACHMENTS_SIZE / 3 + 1, None))
+            .collect::<Vec<_>>();
+        assert_err!(EmailAttachments::try_from(attachments));
+    }
+
+    #[test]
+    fn duplicated_content_ids_are_rejected() {
+        assert_err!(EmailAttachments::try_from(vec![
+            attachment("image/png", 10, Some("logo")),
+            attachment("image/png", 10, Some("logo")),
+        ]));
+        assert_ok!(EmailAttachments::try_from(vec![
+            attachment("image/png", 10, Some("logo")),
+            attachment("image/png", 10, Some("banner")),
+            attachment("application/pdf", 10, None),
+        ]));
+    }
+}
@@ -0,0 +1,59 @@
+use std::convert::TryFrom;
+
+const MAX_LENGTH: usize = 50;
+
+#[derive(Clone, Debug)]
+pub struct IdempotencyKey(String);
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = String;
+
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        if key.trim().is_empty() {
+            Err("The idempotency key cannot be empty".to_string())
+        } else if key.len() > MAX_LENGTH {
+            Err(format!(
+                "The idempotency key must be shorter than {} characters",
+                MAX_LENGTH
+            ))
+        } else {
+            Ok(Self(key))
+        }
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use claim::{
+        assert_err,
+        assert_ok,
+    };
+
+    use super::IdempotencyKey;
+    use super::MAX_LENGTH;
+
+    #[test]
+    fn empty_key_is_invalid() {
+        assert_err!(IdempotencyKey::try_from("".to_string()));
+        assert_err!(IdempotencyKey::try_from(" ".to_string()));
+    }
+
+    #[test]
+    fn too_long_key_is_invalid() {
+        assert_err!(IdempotencyKey::try_from("a".repeat(MAX_LENGTH + 1)));
+    }
+
+    #[test]
+    fn valid_key_is_parsed_successfully() {
+        assert_ok!(IdempotencyKey::try_from("a".repeat(MAX_LENGTH)));
+        assert_ok!(IdempotencyKey::try_from(uuid::Uuid::new_v4().to_string()));
+    }
+}
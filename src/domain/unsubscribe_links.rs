@@ -0,0 +1,145 @@
+use derivative::Derivative;
+use hmac::{
+    Hmac,
+    Mac,
+    NewMac,
+};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Build the one-click unsubscribe links sent with every issue.
+///
+/// The links carry an HMAC of the subscriber id, so that a subscriber can only
+/// be unsubscribed through a link that was sent to them.
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct UnsubscribeLinks {
+    base_url: String,
+    #[derivative(Debug = "ignore")]
+    secret: String,
+    mailbox: Option<String>,
+}
+
+impl UnsubscribeLinks {
+    /// `mailbox` is the address receiving the `mailto:` unsubscribe requests,
+    /// if any.
+    pub fn new(base_url: String, secret: String, mailbox: Option<String>) -> Self {
+        Self {
+            base_url,
+            secret,
+            mailbox,
+        }
+    }
+
+    /// The URL unsubscribing `subscriber_id` when it is posted to.
+    pub fn url(&self, subscriber_id: &Uuid) -> String {
+        format!(
+            "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+            self.base_url,
+            subscriber_id,
+            self.token(subscriber_id)
+        )
+    }
+
+    /// A `mailto:` link to the unsubscribe mailbox, carrying the subscriber id
+    /// and its token in the subject.
+    pub fn mailto(&self, subscriber_id: &Uuid) -> Option<String> {
+        self.mailbox.as_ref().map(|mailbox| {
+            format!(
+                "mailto:{}?subject=unsubscribe-{}-{}",
+                mailbox,
+                subscriber_id,
+                self.token(subscriber_id)
+            )
+        })
+    }
+
+    /// Whether `token` was issued for `subscriber_id`, compared in constant
+    /// time.
+    pub fn verify(&self, subscriber_id: &Uuid, token: &str) -> bool {
+        match base64::decode_config(token, base64::URL_SAFE_NO_PAD) {
+            Ok(token) => self.mac(subscriber_id).verify(&token).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn token(&self, subscriber_id: &Uuid) -> String {
+        base64::encode_config(
+            self.mac(subscriber_id).finalize().into_bytes(),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+
+    fn mac(&self, subscriber_id: &Uuid) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_varkey(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(subscriber_id.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::*;
+
+    fn unsubscribe_links(secret: &str) -> UnsubscribeLinks {
+        UnsubscribeLinks::new(
+            "http://localhost".to_string(),
+            secret.to_string(),
+            Some("unsubscribe@newsletter.com".to_string()),
+        )
+    }
+
+    fn token(url: &str) -> String {
+        Url::parse(url)
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == "token")
+            .unwrap()
+            .1
+            .to_string()
+    }
+
+    #[test]
+    fn tokens_of_the_url_are_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let unsubscribe_links = unsubscribe_links("any_secret");
+
+        let url = unsubscribe_links.url(&subscriber_id);
+
+        assert!(url.starts_with(&format!(
+            "http://localhost/subscriptions/unsubscribe?subscriber_id={}&token=",
+            subscriber_id
+        )));
+        assert!(unsubscribe_links.verify(&subscriber_id, &token(&url)));
+    }
+
+    #[test]
+    fn tokens_of_other_subscribers_or_secrets_are_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let url = unsubscribe_links("any_secret").url(&subscriber_id);
+
+        assert!(!unsubscribe_links("any_secret").verify(&Uuid::new_v4(), &token(&url)));
+        assert!(!unsubscribe_links("other_secret").verify(&subscriber_id, &token(&url)));
+        assert!(!unsubscribe_links("any_secret").verify(&subscriber_id, "not a token"));
+    }
+
+    #[test]
+    fn mailto_carries_the_token_of_the_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let unsubscribe_links = unsubscribe_links("any_secret");
+
+        let mailto = unsubscribe_links.mailto(&subscriber_id).unwrap();
+
+        assert_eq!(
+            mailto,
+            format!(
+                "mailto:unsubscribe@newsletter.com?subject=unsubscribe-{}-{}",
+                subscriber_id,
+                token(&unsubscribe_links.url(&subscriber_id))
+            )
+        );
+    }
+}
@@ -0,0 +1,10 @@
+use derivative::Derivative;
+
+/// The Basic credentials the email provider sends with its event callbacks.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct WebhookCredentials {
+    pub username: String,
+    #[derivative(Debug = "ignore")]
+    pub password: String,
+}
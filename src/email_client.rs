@@ -1,4 +1,56 @@
+pub use circuit_breaker::{
+    CircuitBreaker,
+    CircuitBreakerPolicy,
+    CircuitState,
+};
 pub use client::*;
+pub use dkim::{
+    DkimSigner,
+    DKIM_SIGNATURE_HEADER,
+};
+pub use email::{
+    Email,
+    SentEmail,
+    DEFAULT_SENDER_NAME,
+};
+pub use error::{
+    NotAttempted,
+    ProviderFailure,
+};
+pub use failover::{
+    EmailProvider,
+    FailoverSender,
+};
+pub use metrics::{
+    recipient_hash,
+    render_prometheus,
+    render_remaining_quota,
+    SendMetrics,
+    SendMetricsSnapshot,
+    SendOutcome,
+    LATENCY_BUCKETS,
+};
+pub use outbox::OutboxClient;
+pub use rate_limit::{
+    RateLimit,
+    RateLimiter,
+    RemainingQuota,
+};
+pub use retry::RetryPolicy;
+pub use sender::EmailSender;
+pub use smtp::*;
 
+mod circuit_breaker;
 mod client;
+mod dkim;
+mod email;
+mod error;
+mod failover;
+mod metrics;
+mod outbox;
+mod rate_limit;
 mod request;
+mod response;
+mod retry;
+mod sender;
+mod smtp;
@@ -0,0 +1,423 @@
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::email_client::{
+    Email,
+    EmailSender,
+    NotAttempted,
+    ProviderFailure,
+    RemainingQuota,
+    SendMetricsSnapshot,
+    SentEmail,
+};
+
+/// When a [`CircuitBreaker`] opens, and for how long.
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerPolicy {
+    /// The number of consecutive failed calls opening the circuit.
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a call probes the transport.
+    pub open_duration: Duration,
+}
+
+/// The state of a [`CircuitBreaker`], as reported by `/health_check`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail immediately, without reaching the transport.
+    Open,
+    /// A single call goes through to probe the transport, the others fail
+    /// immediately.
+    HalfOpen,
+}
+
+/// Wrap an [`EmailSender`] so that calls fail fast once it keeps failing,
+/// instead of each of them waiting for the transport timeout.
+///
+/// A call fails when none of its emails is accepted and the provider failed
+/// for some of them, see [`ProviderFailure`]: a rejected message says nothing
+/// about the transport. The calls failing fast are [`NotAttempted`].
+pub struct CircuitBreaker {
+    sender: Arc<dyn EmailSender>,
+    policy: CircuitBreakerPolicy,
+    circuit: Mutex<Circuit>,
+}
+
+#[derive(Clone, Debug)]
+enum Circuit {
+    Closed {
+        consecutive_failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe that has not completed after `open_duration` is given up, so
+    /// that a dropped call cannot keep the circuit half-open.
+    HalfOpen {
+        probe_started_at: Instant,
+    },
+}
+
+/// How a call that went through the circuit affects it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CallOutcome {
+    Succeeded,
+    Failed,
+    /// None of the emails reached the transport, such as when its quota is
+    /// exhausted.
+    NotAttempted,
+}
+
+impl CallOutcome {
+    fn of(results: &[Result<SentEmail, anyhow::Error>]) -> Self {
+        let errors = results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .collect::<Vec<_>>();
+        if errors.len() < results.len() {
+            CallOutcome::Succeeded
+        } else if errors
+            .iter()
+            .any(|error| ProviderFailure::is_cause_of(error))
+        {
+            CallOutcome::Failed
+        } else if errors
+            .iter()
+            .all(|error| NotAttempted::find(error).is_some())
+        {
+            CallOutcome::NotAttempted
+        } else {
+            // the provider answered, rejecting every message
+            CallOutcome::Succeeded
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(sender: Arc<dyn EmailSender>, policy: CircuitBreakerPolicy) -> Self {
+        Self {
+            sender,
+            policy,
+            circuit: Mutex::new(Circuit::Closed {
+                consecutive_failures: 0,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let now = Instant::now();
+        match *self.circuit.lock().unwrap() {
+            Circuit::Closed { .. } => CircuitState::Closed,
+            Circuit::Open { until } if now < until => CircuitState::Open,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Whether a call may go through, turning it into the probe once the
+    /// circuit has been open for `open_duration`.
+    fn try_acquire(&self) -> bool {
+        let now = Instant::now();
+        let mut circuit = self.circuit.lock().unwrap();
+        let can_probe = match *circuit {
+            Circuit::Closed { .. } => return true,
+            Circuit::Open { until } => now >= until,
+            Circuit::HalfOpen { probe_started_at } => {
+                now.saturating_duration_since(probe_started_at) >= self.policy.open_duration
+            }
+        };
+        if can_probe {
+            *circuit = Circuit::HalfOpen {
+                probe_started_at: now,
+            };
+        }
+        can_probe
+    }
+
+    fn record(&self, outcome: CallOutcome) {
+        let mut circuit = self.circuit.lock().unwrap();
+        let now = Instant::now();
+        *circuit = match (&*circuit, outcome) {
+            (_, CallOutcome::Succeeded) => Circuit::Closed {
+                consecutive_failures: 0,
+            },
+            // a probe that did not reach the transport lets the next call probe
+            (Circuit::HalfOpen { .. }, CallOutcome::NotAttempted) => Circuit::Open { until: now },
+            (circuit, CallOutcome::NotAttempted) => circuit.clone(),
+            (
+                Circuit::Closed {
+                    consecutive_failures,
+                },
+                CallOutcome::Failed,
+            ) if consecutive_failures + 1 < self.policy.failure_threshold => Circuit::Closed {
+                consecutive_failures: consecutive_failures + 1,
+            },
+            // a call started before the circuit opened does not extend it
+            (Circuit::Open { until }, CallOutcome::Failed) => Circuit::Open { until: *until },
+            (_, CallOutcome::Failed) => {
+                tracing::warn!(
+                    "Opening the email circuit breaker for {:?}",
+                    self.policy.open_duration
+                );
+                Circuit::Open {
+                    until: now + self.policy.open_duration,
+                }
+            }
+        };
+    }
+}
+
+#[async_trait]
+impl EmailSender for CircuitBreaker {
+    async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+        if emails.is_empty() {
+            return Vec::new();
+        }
+        if !self.try_acquire() {
+            return emails
+                .iter()
+                .map(|email| {
+                    Err(
+                        anyhow::Error::new(NotAttempted::CircuitOpen).context(format!(
+                            "Error sending email to: {} with subject: {}",
+                            email.recipient.as_ref(),
+                            email.subject
+                        )),
+                    )
+                })
+                .collect();
+        }
+        let results = self.sender.send_emails(emails).await;
+        self.record(CallOutcome::of(&results));
+        results
+    }
+
+    fn max_batch_size(&self) -> usize {
+        self.sender.max_batch_size()
+    }
+
+    fn max_concurrent_requests(&self) -> usize {
+        self.sender.max_concurrent_requests()
+    }
+
+    fn remaining_quota(&self) -> Option<RemainingQuota> {
+        self.sender.remaining_quota()
+    }
+
+    fn circuit_state(&self) -> Option<CircuitState> {
+        Some(self.state())
+    }
+
+    fn send_metrics(&self) -> Vec<SendMetricsSnapshot> {
+        self.sender.send_metrics()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::sync::atomic::{
+        AtomicBool,
+        AtomicUsize,
+        Ordering,
+    };
+
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+
+    use crate::domain::SubscriberEmail;
+
+    use super::*;
+
+    /// Fails every call while `failing` is set, and counts the calls reaching
+    /// it.
+    #[derive(Default)]
+    struct FlakySender {
+        failing: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl EmailSender for FlakySender {
+        async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            emails
+                .iter()
+                .map(|_| match self.failing.load(Ordering::SeqCst) {
+                    true => Err(ProviderFailure::new(anyhow::anyhow!("provider down")).into()),
+                    false => Ok(SentEmail::default()),
+                })
+                .collect()
+        }
+    }
+
+    fn email() -> Email<'static> {
+        let recipient = SubscriberEmail::try_from(SafeEmail().fake::<String>()).unwrap();
+        Email::new(recipient, "any_subject", "any_html", "any_text")
+    }
+
+    fn circuit_breaker(sender: Arc<FlakySender>, open_duration: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(
+            sender,
+            CircuitBreakerPolicy {
+                failure_threshold: 3,
+                open_duration,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn the_circuit_opens_after_consecutive_failures_and_fails_fast() {
+        let sender = Arc::new(FlakySender::default());
+        sender.failing.store(true, Ordering::SeqCst);
+        let circuit_breaker = circuit_breaker(sender.clone(), Duration::from_secs(60));
+
+        for _ in 0..3 {
+            assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+            assert!(circuit_breaker.send_email(email()).await.is_err());
+        }
+
+        assert_eq!(circuit_breaker.state(), CircuitState::Open);
+        assert!(circuit_breaker.send_email(email()).await.is_err());
+        assert_eq!(sender.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn a_success_resets_the_consecutive_failures() {
+        let sender = Arc::new(FlakySender::default());
+        let circuit_breaker = circuit_breaker(sender.clone(), Duration::from_secs(60));
+
+        for failing in [true, true, false, true, true].iter() {
+            sender.failing.store(*failing, Ordering::SeqCst);
+            let _ = circuit_breaker.send_email(email()).await;
+        }
+
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn the_circuit_half_opens_and_closes_after_a_successful_probe() {
+        let sender = Arc::new(FlakySender::default());
+        sender.failing.store(true, Ordering::SeqCst);
+        let circuit_breaker = circuit_breaker(sender.clone(), Duration::from_millis(50));
+        for _ in 0..3 {
+            let _ = circuit_breaker.send_email(email()).await;
+        }
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(circuit_breaker.state(), CircuitState::HalfOpen);
+        sender.failing.store(false, Ordering::SeqCst);
+
+        assert!(circuit_breaker.send_email(email()).await.is_ok());
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+        assert_eq!(sender.calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn a_failed_probe_opens_the_circuit_again() {
+        let sender = Arc::new(FlakySender::default());
+        sender.failing.store(true, Ordering::SeqCst);
+        let circuit_breaker = circuit_breaker(sender.clone(), Duration::from_millis(50));
+        for _ in 0..3 {
+            let _ = circuit_breaker.send_email(email()).await;
+        }
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        assert!(circuit_breaker.send_email(email()).await.is_err());
+
+        assert_eq!(circuit_breaker.state(), CircuitState::Open);
+        assert_eq!(sender.calls.load(Ordering::SeqCst), 4);
+    }
+
+    /// Rejects every email, the way a provider answers `4xx`, or fails every
+    /// one of them without reaching the provider.
+    struct FixedErrorSender(fn() -> anyhow::Error);
+
+    #[async_trait]
+    impl EmailSender for FixedErrorSender {
+        async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+            emails.iter().map(|_| Err((self.0)())).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_emails_do_not_open_the_circuit() {
+        let circuit_breaker = CircuitBreaker::new(
+            Arc::new(FixedErrorSender(|| {
+                anyhow::anyhow!("The email provider responded with status: 400 Bad Request")
+            })),
+            CircuitBreakerPolicy {
+                failure_threshold: 1,
+                open_duration: Duration::from_secs(60),
+            },
+        );
+
+        for _ in 0..3 {
+            assert!(circuit_breaker.send_email(email()).await.is_err());
+        }
+
+        assert_eq!(circuit_breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn the_emails_of_an_open_circuit_are_not_attempted() {
+        let sender = Arc::new(FlakySender::default());
+        sender.failing.store(true, Ordering::SeqCst);
+        let circuit_breaker = circuit_breaker(sender, Duration::from_secs(60));
+        for _ in 0..3 {
+            let error = circuit_breaker.send_email(email()).await.unwrap_err();
+            assert_eq!(NotAttempted::find(&error), None);
+        }
+
+        let error = circuit_breaker.send_email(email()).await.unwrap_err();
+
+        assert_eq!(NotAttempted::find(&error), Some(NotAttempted::CircuitOpen));
+    }
+
+    #[tokio::test]
+    async fn a_probe_that_is_not_attempted_lets_the_next_call_probe() {
+        let circuit_breaker = CircuitBreaker::new(
+            Arc::new(FixedErrorSender(|| {
+                NotAttempted::QuotaExhausted(Duration::from_secs(60)).into()
+            })),
+            CircuitBreakerPolicy {
+                failure_threshold: 1,
+                open_duration: Duration::from_secs(60),
+            },
+        );
+        *circuit_breaker.circuit.lock().unwrap() = Circuit::Open {
+            until: Instant::now(),
+        };
+
+        let error = circuit_breaker.send_email(email()).await.unwrap_err();
+
+        assert!(matches!(
+            NotAttempted::find(&error),
+            Some(NotAttempted::QuotaExhausted(_))
+        ));
+        assert!(circuit_breaker.try_acquire());
+    }
+
+    #[test]
+    fn only_one_probe_goes_through_while_half_open() {
+        let circuit_breaker =
+            circuit_breaker(Arc::new(FlakySender::default()), Duration::from_secs(60));
+        *circuit_breaker.circuit.lock().unwrap() = Circuit::Open {
+            until: Instant::now(),
+        };
+
+        assert!(circuit_breaker.try_acquire());
+        assert!(!circuit_breaker.try_acquire());
+        assert_eq!(circuit_breaker.state(), CircuitState::HalfOpen);
+    }
+}
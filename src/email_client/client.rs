@@ -1,22 +1,85 @@
-use std::time::Duration;
+use std::time::{
+    Duration,
+    Instant,
+};
 
 use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{
+    DateTime,
+    Utc,
+};
 use derivative::Derivative;
+use futures::{
+    stream,
+    StreamExt,
+};
+use rand::Rng;
+use reqwest::header::{
+    HeaderMap,
+    RETRY_AFTER,
+};
 use reqwest::{
     Client,
+    StatusCode,
     Url,
 };
 
 use crate::domain::SubscriberEmail;
-use crate::email_client::request::EmailRequest;
+use crate::email_client::request::{
+    EmailRequest,
+    Sender,
+};
+use crate::email_client::response::EmailResponse;
+use crate::email_client::{
+    recipient_hash,
+    Email,
+    EmailSender,
+    NotAttempted,
+    ProviderFailure,
+    RateLimit,
+    RateLimiter,
+    RemainingQuota,
+    RetryPolicy,
+    SendMetrics,
+    SendMetricsSnapshot,
+    SendOutcome,
+    SentEmail,
+    DEFAULT_SENDER_NAME,
+};
 
-#[derive(Derivative, Debug)]
+/// The provider the metrics are recorded under, unless set with
+/// [`EmailClient::with_provider`].
+const DEFAULT_PROVIDER: &str = "mailjet";
+
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct EmailClient {
     http_client: Client,
     base_url: Url,
     sender: SubscriberEmail,
+    sender_name: String,
+    reply_to: Option<SubscriberEmail>,
     #[derivative(Debug = "ignore")]
     token: String,
+    retry_policy: RetryPolicy,
+    max_batch_size: usize,
+    max_concurrent_requests: usize,
+    rate_limiter: Option<RateLimiter>,
+    provider: String,
+    metrics: SendMetrics,
+    /// The key of the [`recipient_hash`] of the logs.
+    #[derivative(Debug = "ignore")]
+    recipient_hash_key: Vec<u8>,
+}
+
+/// A failed attempt to hand an email over to the provider.
+struct SendFailure {
+    error: anyhow::Error,
+    /// Whether a later attempt may succeed: timeouts, 5xx, 408 and 429.
+    is_transient: bool,
+    /// The delay requested by the provider with a `Retry-After` header.
+    retry_after: Option<Duration>,
 }
 
 impl EmailClient {
@@ -40,43 +103,348 @@ impl EmailClient {
                 ))?,
             base_url,
             sender,
+            sender_name: DEFAULT_SENDER_NAME.to_string(),
+            reply_to: None,
             token,
+            retry_policy: RetryPolicy::none(),
+            max_batch_size: 1,
+            max_concurrent_requests: 1,
+            rate_limiter: None,
+            provider: DEFAULT_PROVIDER.to_string(),
+            metrics: SendMetrics::default(),
+            recipient_hash_key: rand::thread_rng().gen::<[u8; 32]>().to_vec(),
         })
     }
 
-    pub async fn send_email(
+    /// Send the emails under `sender_name` instead of the
+    /// [default](DEFAULT_SENDER_NAME) one.
+    pub fn with_sender_name(mut self, sender_name: String) -> Self {
+        self.sender_name = sender_name;
+        self
+    }
+
+    /// Direct the replies of the recipients to `reply_to` instead of the
+    /// sender.
+    pub fn with_reply_to(mut self, reply_to: SubscriberEmail) -> Self {
+        self.reply_to = Some(reply_to);
+        self
+    }
+
+    /// Retry transient failures according to `retry_policy`.
+    ///
+    /// By default every email is attempted only once.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Pack up to `max_batch_size` messages in each provider call made by
+    /// [`send_emails`](EmailSender::send_emails).
+    ///
+    /// By default every email is sent with its own call.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+
+    /// Let [`send_emails`](EmailSender::send_emails) keep up to
+    /// `max_concurrent_requests` provider calls in flight.
+    ///
+    /// By default the batches are sent one after the other.
+    pub fn with_max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+        self.max_concurrent_requests = max_concurrent_requests.max(1);
+        self
+    }
+
+    /// Hold every provider call, retries included, to `rate_limit`.
+    ///
+    /// By default the calls are not limited.
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(rate_limit));
+        self
+    }
+
+    /// Record the metrics and the tracing spans under the `provider` name.
+    pub fn with_provider(mut self, provider: String) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Key the [`recipient_hash`] of the logs with `key`.
+    ///
+    /// By default the key is random, the hashes of a recipient only match
+    /// within the logs of the same process.
+    pub fn with_recipient_hash_key(mut self, key: Vec<u8>) -> Self {
+        self.recipient_hash_key = key;
+        self
+    }
+
+    fn sender(&self) -> Sender<'_> {
+        Sender {
+            email: self.sender.as_ref(),
+            name: &self.sender_name,
+            reply_to: self.reply_to.as_ref().map(AsRef::as_ref),
+        }
+    }
+
+    #[tracing::instrument(
+    name = "Sending emails",
+    skip(self, batch),
+    fields(
+    provider = % self.provider,
+    recipients = % recipient_hashes(&self.recipient_hash_key, batch),
+    outcome = tracing::field::Empty,
+    )
+    )]
+    async fn send_batch(&self, batch: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+        let request = EmailRequest::from_emails(self.sender(), batch);
+        let message_results: Vec<Result<SentEmail, anyhow::Error>> =
+            match self.send_with_retry(&request).await {
+                // the provider accepted the request without detailing each message
+                Ok(response) if response.messages.is_empty() => {
+                    batch.iter().map(|_| Ok(SentEmail::default())).collect()
+                }
+                Ok(response) => {
+                    let mut messages = response.messages.into_iter();
+                    batch
+                        .iter()
+                        .map(|_| match messages.next() {
+                            Some(message) => message.into_result(),
+                            None => Err(anyhow::anyhow!(
+                                "The email provider returned no result for the message"
+                            )),
+                        })
+                        .collect()
+                }
+                Err(e) => batch.iter().map(|_| Err(copy_error(&e))).collect(),
+            };
+        message_results
+            .into_iter()
+            .zip(batch)
+            .map(|(result, email)| {
+                result.with_context(|| {
+                    format!(
+                        "Error sending email to: {} with subject: {}",
+                        email.recipient.as_ref(),
+                        email.subject
+                    )
+                })
+            })
+            .collect()
+    }
+
+    async fn send_with_retry(
         &self,
-        recipient: SubscriberEmail,
-        subject: &str,
-        html_part: &str,
-        text_part: &str,
-    ) -> Result<(), anyhow::Error> {
-        let smtp_response = self
+        request: &EmailRequest<'_>,
+    ) -> Result<EmailResponse, anyhow::Error> {
+        let mut attempt = 1;
+        loop {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire(request.messages.len()).await?;
+            }
+            let failure = match self.try_send(request).await {
+                Ok(response) => return Ok(response),
+                Err(failure) => failure,
+            };
+            let delay = failure
+                .retry_after
+                .unwrap_or_else(|| self.retry_policy.delay_after(attempt));
+            // a `Retry-After` longer than `max_delay` is honoured by not retrying at all
+            if !failure.is_transient
+                || attempt >= self.retry_policy.max_attempts
+                || delay > self.retry_policy.max_delay
+            {
+                return Err(failure
+                    .error
+                    .context(format!("Giving up after {} attempt(s)", attempt)));
+            }
+            tracing::warn!(
+                "Attempt {} to send email failed, retrying in {:?}: {:#}",
+                attempt,
+                delay,
+                failure.error
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn try_send(&self, request: &EmailRequest<'_>) -> Result<EmailResponse, SendFailure> {
+        let endpoint = self.base_url.join("send").map_err(|e| SendFailure {
+            error: e.into(),
+            is_transient: false,
+            retry_after: None,
+        })?;
+        let started_at = Instant::now();
+        let response = self
             .http_client
-            .post(self.base_url.join("send")?)
+            .post(endpoint)
             .header("Content-Type", "application/json")
             .header("Authorization", self.token.as_str())
-            .json(&EmailRequest::new(
-                self.sender.as_ref(),
-                recipient.as_ref(),
-                subject,
-                html_part,
-                text_part,
-            ))
+            .json(request)
             .send()
-            .await?;
-        smtp_response.error_for_status().context(format!(
-            "Error sending email to: {} with subject: {}",
-            recipient.as_ref(),
-            subject
-        ))?;
-        Ok(())
+            .await
+            .map_err(|e| {
+                let outcome = match e.is_timeout() {
+                    true => SendOutcome::Timeout,
+                    false => SendOutcome::Error,
+                };
+                self.record(outcome, started_at);
+                let is_transient = e.is_timeout() || e.is_connect();
+                SendFailure {
+                    // nothing reaches the provider before it is connected
+                    error: match (e.is_connect(), is_transient) {
+                        (true, _) => ProviderFailure::unsent(e).into(),
+                        (false, true) => ProviderFailure::new(e).into(),
+                        (false, false) => e.into(),
+                    },
+                    is_transient,
+                    retry_after: None,
+                }
+            })?;
+        let status = response.status();
+        let is_transient = status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::REQUEST_TIMEOUT;
+        let retry_after = if status == StatusCode::TOO_MANY_REQUESTS {
+            parse_retry_after(response.headers())
+        } else {
+            None
+        };
+        let body = response.bytes().await.unwrap_or_default();
+        self.record(SendOutcome::from_status(status), started_at);
+        let email_response = serde_json::from_slice::<EmailResponse>(&body).ok();
+        match email_response {
+            _ if status.is_success() => Ok(email_response.unwrap_or_default()),
+            // the provider rejected some of the messages and reported why for each of them
+            Some(email_response)
+                if status == StatusCode::BAD_REQUEST && !email_response.messages.is_empty() =>
+            {
+                Ok(email_response)
+            }
+            _ => {
+                let error = anyhow::anyhow!("The email provider responded with status: {}", status);
+                Err(SendFailure {
+                    error: match status.is_server_error() {
+                        true => ProviderFailure::new(error).into(),
+                        false => error,
+                    },
+                    is_transient,
+                    retry_after,
+                })
+            }
+        }
+    }
+
+    /// Record a call in the metrics and in the span of its batch.
+    fn record(&self, outcome: SendOutcome, started_at: Instant) {
+        self.metrics.record(outcome, started_at.elapsed());
+        tracing::Span::current().record("outcome", &outcome.label());
+    }
+}
+
+/// The [`recipient_hash`] of each email of `batch`, keyed by `key`.
+fn recipient_hashes(key: &[u8], batch: &[Email<'_>]) -> String {
+    batch
+        .iter()
+        .map(|email| recipient_hash(key, email.recipient.as_ref()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A copy of the error of a batch for one of its emails, telling whether it
+/// was attempted and whether the provider failed.
+fn copy_error(error: &anyhow::Error) -> anyhow::Error {
+    if let Some(not_attempted) = NotAttempted::find(error) {
+        return not_attempted.into();
+    }
+    match ProviderFailure::find(error) {
+        Some(failure) => failure.with_cause(anyhow::anyhow!("{:#}", error)).into(),
+        None => anyhow::anyhow!("{:#}", error),
+    }
+}
+
+/// Parse a `Retry-After` header given either in seconds or as an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let retry_after = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = retry_after.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let retry_at = DateTime::parse_from_rfc2822(retry_after).ok()?;
+    Some(
+        (retry_at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_else(|_| Duration::from_secs(0)),
+    )
+}
+
+/// Split `emails` in batches of at most `max_batch_size` emails, in order,
+/// each email with attachments in a batch of its own.
+fn batches<'e, 'a>(emails: &'e [Email<'a>], max_batch_size: usize) -> Vec<&'e [Email<'a>]> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    for (i, email) in emails.iter().enumerate() {
+        if !email.attachments.is_empty() {
+            batches.extend(emails[start..i].chunks(max_batch_size));
+            batches.push(&emails[i..i + 1]);
+            start = i + 1;
+        }
+    }
+    batches.extend(emails[start..].chunks(max_batch_size));
+    batches
+}
+
+#[async_trait]
+impl EmailSender for EmailClient {
+    /// Send `emails` in batches of at most `max_batch_size` messages, with up
+    /// to `max_concurrent_requests` batches in flight.
+    ///
+    /// Emails with attachments are sent alone: each message of a batch
+    /// carries its own encoded copy of the attachments.
+    ///
+    /// It returns one result for each email, in the same order, so that a
+    /// message rejected by the provider does not fail the rest of its batch.
+    async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+        // the futures are built upfront: the compiler cannot prove that a lazy
+        // `map` over the chunks is `Send`
+        let batches = batches(emails, self.max_batch_size)
+            .into_iter()
+            .map(|batch| self.send_batch(batch))
+            .collect::<Vec<_>>();
+        stream::iter(batches)
+            .buffered(self.max_concurrent_requests)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    fn max_concurrent_requests(&self) -> usize {
+        self.max_concurrent_requests
+    }
+
+    fn remaining_quota(&self) -> Option<RemainingQuota> {
+        self.rate_limiter.as_ref().map(RateLimiter::remaining_quota)
+    }
+
+    fn send_metrics(&self) -> Vec<SendMetricsSnapshot> {
+        self.metrics.snapshot(&self.provider)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::convert::TryFrom;
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+    use std::sync::Arc;
 
     use claim::assert_ok;
     use fake::faker::internet::en::SafeEmail;
@@ -89,7 +457,11 @@ mod tests {
         StatusCode,
         Url,
     };
-    use wiremock::matchers::body_json;
+    use tokio::net::TcpListener;
+    use wiremock::matchers::{
+        body_json,
+        body_string_contains,
+    };
     use wiremock::matchers::{
         header,
         method,
@@ -98,10 +470,15 @@ mod tests {
     use wiremock::{
         Mock,
         MockServer,
+        Request,
+        Respond,
         ResponseTemplate,
     };
 
-    use crate::domain::SubscriberEmail;
+    use crate::domain::{
+        EmailAttachment,
+        SubscriberEmail,
+    };
 
     use super::*;
 
@@ -122,6 +499,25 @@ mod tests {
         String::from("token")
     }
 
+    #[test]
+    fn the_token_and_the_recipient_hash_key_are_not_debug_printed() {
+        let email_client = EmailClient::new(
+            Url::parse("https://any_url").unwrap(),
+            email(),
+            "any_token".to_string(),
+            10,
+        )
+        .unwrap()
+        .with_recipient_hash_key(b"any_secret".to_vec());
+
+        let debug = format!("{:?}", email_client);
+
+        assert!(debug.contains("any_url"));
+        assert!(!debug.contains("any_token"));
+        assert!(!debug.contains("any_secret"));
+        assert!(!debug.contains(&format!("{:?}", b"any_secret".to_vec())));
+    }
+
     #[tokio::test]
     async fn email_client_performs_the_correct_request() {
         let token = token();
@@ -136,12 +532,13 @@ mod tests {
             .and(path("/send"))
             .and(header("Content-Type", "application/json"))
             .and(header("Authorization", token.as_str()))
-            .and(body_json(&EmailRequest::new(
-                sender.as_ref(),
-                recipient.as_ref(),
-                &subject,
-                &content,
-                &content,
+            .and(body_json(EmailRequest::from_emails(
+                Sender {
+                    email: sender.as_ref(),
+                    name: DEFAULT_SENDER_NAME,
+                    reply_to: None,
+                },
+                &[Email::new(recipient.clone(), &subject, &content, &content)],
             )))
             .respond_with(ResponseTemplate::new(200))
             .expect(1)
@@ -158,11 +555,154 @@ mod tests {
 
         assert_ok!(
             email_client
-                .send_email(recipient, &subject, &content, &content)
+                .send_email(Email::new(recipient, &subject, &content, &content))
                 .await
         );
     }
 
+    #[tokio::test]
+    async fn email_client_sends_the_sender_identity_and_the_recipient_name() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let sender = email();
+        let reply_to = email();
+        let recipient = email();
+        let email_client = EmailClient::new(
+            Url::parse(&server.uri()).unwrap(),
+            sender.clone(),
+            token(),
+            10,
+        )
+        .unwrap()
+        .with_sender_name("Weekly Digest".to_string())
+        .with_reply_to(reply_to.clone());
+
+        let outcome = email_client
+            .send_email(Email {
+                recipient_name: Some("Ursula Le Guin"),
+                custom_id: Some("issue:subscriber".to_string()),
+                ..Email::new(recipient.clone(), "any_subject", "any_html", "any_text")
+            })
+            .await;
+
+        assert_ok!(outcome);
+        let request = &server.received_requests().await.unwrap()[0];
+        let message =
+            &serde_json::from_slice::<serde_json::Value>(&request.body).unwrap()["Messages"][0];
+        assert_eq!(
+            message["From"],
+            serde_json::json!({ "Email": sender.as_ref(), "Name": "Weekly Digest" })
+        );
+        assert_eq!(
+            message["To"],
+            serde_json::json!([{ "Email": recipient.as_ref(), "Name": "Ursula Le Guin" }])
+        );
+        assert_eq!(
+            message["ReplyTo"],
+            serde_json::json!({ "Email": reply_to.as_ref() })
+        );
+        assert_eq!(message["CustomID"], "issue:subscriber");
+    }
+
+    #[tokio::test]
+    async fn email_client_sends_the_extra_headers() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10).unwrap();
+
+        let outcome = email_client
+            .send_email(Email {
+                headers: vec![
+                    ("List-Unsubscribe", "<https://any_url>".to_string()),
+                    (
+                        "List-Unsubscribe-Post",
+                        "List-Unsubscribe=One-Click".to_string(),
+                    ),
+                ],
+                ..Email::new(email(), "any_subject", "any_html", "any_text")
+            })
+            .await;
+
+        assert_ok!(outcome);
+        let request = &server.received_requests().await.unwrap()[0];
+        let message =
+            &serde_json::from_slice::<serde_json::Value>(&request.body).unwrap()["Messages"][0];
+        assert_eq!(
+            message["Headers"],
+            serde_json::json!({
+                "List-Unsubscribe": "<https://any_url>",
+                "List-Unsubscribe-Post": "List-Unsubscribe=One-Click",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn email_client_sends_attachments_and_inline_images() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10).unwrap();
+        let attachments = [
+            EmailAttachment::parse(
+                "report.pdf".to_string(),
+                "application/pdf".to_string(),
+                b"any_pdf".to_vec(),
+                None,
+            )
+            .unwrap(),
+            EmailAttachment::parse(
+                "logo.png".to_string(),
+                "image/png".to_string(),
+                b"any_png".to_vec(),
+                Some("logo".to_string()),
+            )
+            .unwrap(),
+        ];
+        let email = Email {
+            attachments: &attachments,
+            ..Email::new(email(), "any_subject", "<img src=\"cid:logo\">", "any_text")
+        };
+        let results = email_client.send_emails(std::slice::from_ref(&email)).await;
+
+        assert!(results.iter().all(Result::is_ok));
+        let request = &server.received_requests().await.unwrap()[0];
+        let message =
+            &serde_json::from_slice::<serde_json::Value>(&request.body).unwrap()["Messages"][0];
+        assert_eq!(
+            message["Attachments"],
+            serde_json::json!([{
+                "ContentType": "application/pdf",
+                "Filename": "report.pdf",
+                "Base64Content": base64::encode("any_pdf"),
+            }])
+        );
+        assert_eq!(
+            message["InlinedAttachments"],
+            serde_json::json!([{
+                "ContentType": "image/png",
+                "Filename": "logo.png",
+                "ContentID": "logo",
+                "Base64Content": base64::encode("any_png"),
+            }])
+        );
+    }
+
     #[tokio::test]
     async fn email_client_handles_error_response() {
         for status_code in [StatusCode::INTERNAL_SERVER_ERROR, StatusCode::NOT_FOUND].iter() {
@@ -178,13 +718,487 @@ mod tests {
                 EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10).unwrap();
 
             let response = email_client
-                .send_email(email(), &sentence(), &paragraph(), &paragraph())
+                .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
                 .await;
 
             assert!(response.is_err());
         }
     }
 
+    #[tokio::test]
+    async fn only_server_and_connection_errors_are_provider_failures() {
+        let send_email = |base_url: Url| async move {
+            EmailClient::new(base_url, email(), token(), 10)
+                .unwrap()
+                .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+                .await
+                .unwrap_err()
+        };
+        for (status_code, is_provider_failure) in [
+            (StatusCode::INTERNAL_SERVER_ERROR, true),
+            (StatusCode::BAD_REQUEST, false),
+            (StatusCode::TOO_MANY_REQUESTS, false),
+        ]
+        .iter()
+        {
+            let server = MockServer::start().await;
+            Mock::given(method("POST"))
+                .respond_with(ResponseTemplate::new(status_code.as_u16()))
+                .mount(&server)
+                .await;
+
+            let error = send_email(Url::parse(&server.uri()).unwrap()).await;
+
+            assert_eq!(
+                ProviderFailure::is_cause_of(&error),
+                *is_provider_failure,
+                "{}",
+                status_code
+            );
+            // the provider answered, it may have sent the email
+            if let Some(failure) = ProviderFailure::find(&error) {
+                assert!(failure.may_be_sent());
+            }
+        }
+
+        // nothing listens on the port of a dropped listener
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let error = send_email(Url::parse(&base_url).unwrap()).await;
+        assert!(!ProviderFailure::find(&error).unwrap().may_be_sent());
+    }
+
+    fn retry_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(2),
+            jitter: 0.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn email_client_retries_transient_errors() {
+        for status_code in [
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::TOO_MANY_REQUESTS,
+        ]
+        .iter()
+        {
+            let server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .respond_with(ResponseTemplate::new(status_code.as_u16()))
+                .up_to_n_times(2)
+                .expect(2)
+                .mount(&server)
+                .await;
+            Mock::given(method("POST"))
+                .respond_with(ResponseTemplate::new(200))
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let email_client =
+                EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                    .unwrap()
+                    .with_retry_policy(retry_policy(3));
+
+            assert_ok!(
+                email_client
+                    .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+                    .await
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn email_client_gives_up_after_max_attempts() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_retry_policy(retry_policy(3));
+
+        let response = email_client
+            .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+            .await;
+
+        assert!(response.is_err());
+    }
+
+    #[tokio::test]
+    async fn email_client_does_not_retry_permanent_errors() {
+        for status_code in [
+            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::NOT_FOUND,
+        ]
+        .iter()
+        {
+            let server = MockServer::start().await;
+
+            Mock::given(method("POST"))
+                .respond_with(ResponseTemplate::new(status_code.as_u16()))
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let email_client =
+                EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                    .unwrap()
+                    .with_retry_policy(retry_policy(3));
+
+            let response = email_client
+                .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+                .await;
+
+            assert!(response.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn email_client_honours_retry_after() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_retry_policy(retry_policy(3));
+
+        let start = std::time::Instant::now();
+        assert_ok!(
+            email_client
+                .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+                .await
+        );
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn email_client_does_not_retry_before_a_too_long_retry_after() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_retry_policy(retry_policy(3));
+
+        let response = email_client
+            .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+            .await;
+
+        assert!(response.is_err());
+    }
+
+    #[tokio::test]
+    async fn email_client_packs_emails_in_batches() {
+        let server = MockServer::start().await;
+        let subject = sentence();
+        let content = paragraph();
+        let emails = (0..5)
+            .map(|_| Email::new(email(), &subject, &content, &content))
+            .collect::<Vec<_>>();
+
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_max_batch_size(2);
+
+        let results = email_client.send_emails(&emails).await;
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(Result::is_ok));
+
+        let batch_sizes = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| {
+                serde_json::from_slice::<serde_json::Value>(&request.body).unwrap()["Messages"]
+                    .as_array()
+                    .unwrap()
+                    .len()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(batch_sizes, vec![2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn email_client_sends_emails_with_attachments_alone() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(4)
+            .mount(&server)
+            .await;
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_max_batch_size(50);
+        let attachments = [EmailAttachment::parse(
+            "report.pdf".to_string(),
+            "application/pdf".to_string(),
+            b"any_pdf".to_vec(),
+            None,
+        )
+        .unwrap()];
+        let emails = (0..5)
+            .map(|i| {
+                let email = Email::new(email(), "any_subject", "any_html", "any_text");
+                if i == 1 || i == 2 {
+                    Email {
+                        attachments: &attachments,
+                        ..email
+                    }
+                } else {
+                    email
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let results = email_client.send_emails(&emails).await;
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(Result::is_ok));
+
+        let batches = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| {
+                serde_json::from_slice::<serde_json::Value>(&request.body).unwrap()["Messages"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|message| message["Attachments"].as_array().map_or(0, Vec::len))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(batches, vec![vec![0], vec![1], vec![1], vec![0, 0]]);
+    }
+
+    /// Answer every request with `200 OK` after `delay`, recording the
+    /// maximum number of requests in flight.
+    struct InFlightResponder {
+        delay: Duration,
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl Respond for InFlightResponder {
+        fn respond(&self, _: &Request) -> ResponseTemplate {
+            let requests = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(requests, Ordering::SeqCst);
+            // the request is no longer counted before its response is sent, so
+            // that the next request of its slot is never counted along with it
+            let in_flight = self.in_flight.clone();
+            let counted = self.delay / 2;
+            std::thread::spawn(move || {
+                std::thread::sleep(counted);
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+            });
+            ResponseTemplate::new(200).set_delay(self.delay)
+        }
+    }
+
+    #[tokio::test]
+    async fn email_client_bounds_the_requests_in_flight() {
+        let subject = sentence();
+        let content = paragraph();
+        let emails = (0..8)
+            .map(|_| Email::new(email(), &subject, &content, &content))
+            .collect::<Vec<_>>();
+
+        for max_concurrent_requests in [1, 2, 4].iter() {
+            let server = MockServer::start().await;
+            let max_in_flight = Arc::new(AtomicUsize::new(0));
+            Mock::given(method("POST"))
+                .respond_with(InFlightResponder {
+                    delay: Duration::from_millis(100),
+                    in_flight: Arc::new(AtomicUsize::new(0)),
+                    max_in_flight: max_in_flight.clone(),
+                })
+                .mount(&server)
+                .await;
+            let email_client =
+                EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                    .unwrap()
+                    .with_max_concurrent_requests(*max_concurrent_requests);
+
+            let results = email_client.send_emails(&emails).await;
+
+            assert!(results.iter().all(Result::is_ok));
+            // the requests of a round are all sent before the first response
+            assert_eq!(
+                max_in_flight.load(Ordering::SeqCst),
+                *max_concurrent_requests
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn email_client_attributes_concurrent_results_to_each_recipient() {
+        let server = MockServer::start().await;
+        let subject = sentence();
+        let content = paragraph();
+        let emails = (0..4)
+            .map(|_| Email::new(email(), &subject, &content, &content))
+            .collect::<Vec<_>>();
+
+        // the first recipient is answered last
+        Mock::given(method("POST"))
+            .and(body_string_contains(emails[0].recipient.as_ref()))
+            .respond_with(ResponseTemplate::new(400).set_delay(Duration::from_millis(300)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_max_concurrent_requests(4);
+
+        let results = email_client.send_emails(&emails).await;
+        let error = format!("{:#}", results[0].as_ref().unwrap_err());
+        assert!(error.contains(emails[0].recipient.as_ref()));
+        assert!(results[1..].iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn email_client_reports_partial_failures_for_each_recipient() {
+        let server = MockServer::start().await;
+        let subject = sentence();
+        let content = paragraph();
+        let emails = (0..2)
+            .map(|_| Email::new(email(), &subject, &content, &content))
+            .collect::<Vec<_>>();
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "Messages": [
+                    {"Status": "success", "To": []},
+                    {
+                        "Status": "error",
+                        "Errors": [{
+                            "ErrorCode": "mj-0013",
+                            "StatusCode": 400,
+                            "ErrorMessage": "\"invalid\" is an invalid email address.",
+                        }]
+                    }
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_max_batch_size(2);
+
+        let results = email_client.send_emails(&emails).await;
+        assert_ok!(&results[0]);
+        let error = format!("{:#}", results[1].as_ref().unwrap_err());
+        assert!(error.contains(emails[1].recipient.as_ref()));
+        assert!(error.contains("mj-0013"));
+    }
+
+    #[tokio::test]
+    async fn email_client_returns_the_message_id_of_each_recipient() {
+        let server = MockServer::start().await;
+        let subject = sentence();
+        let content = paragraph();
+        let emails = (0..2)
+            .map(|_| Email::new(email(), &subject, &content, &content))
+            .collect::<Vec<_>>();
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Messages": [
+                    {
+                        "Status": "success",
+                        "To": [{
+                            "Email": emails[0].recipient.as_ref(),
+                            "MessageUUID": "123",
+                            "MessageID": 1152921504606846976u64,
+                            "MessageHref": "https://api.mailjet.com/v3/message/1152921504606846976",
+                        }]
+                    },
+                    {
+                        "Status": "success",
+                        "To": [{
+                            "Email": emails[1].recipient.as_ref(),
+                            "MessageUUID": "456",
+                            "MessageID": 1152921504606846977u64,
+                            "MessageHref": "https://api.mailjet.com/v3/message/1152921504606846977",
+                        }]
+                    }
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_max_batch_size(2);
+
+        let results = email_client.send_emails(&emails).await;
+        let message_ids = results
+            .into_iter()
+            .map(|result| result.unwrap().message_id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            message_ids,
+            vec![
+                Some("1152921504606846976".to_string()),
+                Some("1152921504606846977".to_string())
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn email_client_handles_timeout() {
         let server = MockServer::start().await;
@@ -206,9 +1220,130 @@ mod tests {
         .unwrap();
 
         let response = email_client
-            .send_email(email(), &sentence(), &paragraph(), &paragraph())
+            .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
             .await;
 
         assert!(response.is_err());
+        assert_eq!(calls(&email_client, SendOutcome::Timeout), 1);
+    }
+
+    fn calls(email_client: &EmailClient, outcome: SendOutcome) -> u64 {
+        email_client
+            .send_metrics()
+            .into_iter()
+            .find(|metrics| metrics.outcome == outcome)
+            .unwrap()
+            .calls
+    }
+
+    #[tokio::test]
+    async fn email_client_records_each_attempt_by_outcome() {
+        let server = MockServer::start().await;
+        for status in [500, 429, 200].iter() {
+            Mock::given(method("POST"))
+                .respond_with(ResponseTemplate::new(*status))
+                .up_to_n_times(1)
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_retry_policy(retry_policy(3))
+                .with_provider("mailjet-eu".to_string());
+
+        assert_ok!(
+            email_client
+                .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+                .await
+        );
+
+        let send_metrics = email_client.send_metrics();
+        assert!(send_metrics
+            .iter()
+            .all(|metrics| metrics.provider == "mailjet-eu"));
+        assert_eq!(calls(&email_client, SendOutcome::ServerError), 1);
+        assert_eq!(calls(&email_client, SendOutcome::ClientError), 1);
+        assert_eq!(calls(&email_client, SendOutcome::Success), 1);
+        assert_eq!(calls(&email_client, SendOutcome::Timeout), 0);
+    }
+
+    fn rate_limit(messages_per_second: f64, burst: u32) -> RateLimit {
+        RateLimit {
+            messages_per_second,
+            burst,
+            messages_per_day: None,
+            max_wait: Duration::from_secs(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn email_client_holds_concurrent_sends_to_the_rate_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(3)
+            .mount(&server)
+            .await;
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_rate_limit(rate_limit(20.0, 1));
+        let (subject, content) = (sentence(), paragraph());
+        let start = std::time::Instant::now();
+
+        let results =
+            futures::future::join_all((0..3).map(|_| {
+                email_client.send_email(Email::new(email(), &subject, &content, &content))
+            }))
+            .await;
+
+        assert!(results.iter().all(Result::is_ok));
+        // the burst covers the first send, the two others wait 50ms each
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn email_client_fails_without_calling_the_provider_when_the_quota_is_exhausted() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let email_client =
+            EmailClient::new(Url::parse(&server.uri()).unwrap(), email(), token(), 10)
+                .unwrap()
+                .with_rate_limit(RateLimit {
+                    messages_per_day: Some(1),
+                    ..rate_limit(1.0, 10)
+                });
+        let (subject, content) = (sentence(), paragraph());
+
+        assert_ok!(
+            email_client
+                .send_email(Email::new(email(), &subject, &content, &content))
+                .await
+        );
+        assert_eq!(email_client.remaining_quota().unwrap().day, Some(0));
+        let error = email_client
+            .send_email(Email::new(email(), &subject, &content, &content))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            NotAttempted::find(&error),
+            Some(NotAttempted::QuotaExhausted(_))
+        ));
+    }
+
+    #[test]
+    fn email_client_without_rate_limit_has_no_quota() {
+        let email_client =
+            EmailClient::new(Url::parse("https://any_url").unwrap(), email(), token(), 10).unwrap();
+
+        assert_eq!(email_client.remaining_quota(), None);
     }
 }
@@ -0,0 +1,60 @@
+use crate::domain::{
+    EmailAttachment,
+    SubscriberEmail,
+};
+
+/// The display name of the sender, unless configured otherwise.
+pub const DEFAULT_SENDER_NAME: &str = "Newsletter";
+
+/// A single message handed over to the
+/// [`EmailSender`](crate::email_client::EmailSender).
+#[derive(Clone, Debug)]
+pub struct Email<'a> {
+    pub recipient: SubscriberEmail,
+    /// The display name of the recipient.
+    pub recipient_name: Option<&'a str>,
+    pub subject: &'a str,
+    pub html_part: &'a str,
+    pub text_part: &'a str,
+    pub attachments: &'a [EmailAttachment],
+    /// A correlation id, such as the issue and subscriber the email is sent
+    /// for, attached to the message and reported back by the provider.
+    pub custom_id: Option<String>,
+    /// Extra headers of the message, such as `List-Unsubscribe`.
+    pub headers: Vec<(&'static str, String)>,
+}
+
+impl<'a> Email<'a> {
+    /// An email without recipient name, attachments, correlation id or extra
+    /// headers.
+    pub fn new(
+        recipient: SubscriberEmail,
+        subject: &'a str,
+        html_part: &'a str,
+        text_part: &'a str,
+    ) -> Self {
+        Self {
+            recipient,
+            recipient_name: None,
+            subject,
+            html_part,
+            text_part,
+            attachments: &[],
+            custom_id: None,
+            headers: Vec::new(),
+        }
+    }
+}
+
+/// The outcome of an email accepted by an
+/// [`EmailSender`](crate::email_client::EmailSender).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SentEmail {
+    /// The id given to the message by the provider, to match the delivery
+    /// events it reports later on. `None` when the provider did not return
+    /// one.
+    pub message_id: Option<String>,
+    /// The name of the provider that carried the message, set by the
+    /// [`FailoverSender`](crate::email_client::FailoverSender).
+    pub provider: Option<String>,
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+/// Why an email was not handed to the provider: it can be sent again later
+/// as is, and is not a failed delivery.
+#[derive(Clone, Copy, Debug, PartialEq, thiserror::Error)]
+pub enum NotAttempted {
+    #[error("The email circuit breaker is open")]
+    CircuitOpen,
+    #[error("The email quota is exhausted, it is refilled in {0:?}")]
+    QuotaExhausted(Duration),
+}
+
+impl NotAttempted {
+    /// Why the email failing with `error` was not attempted, if it was not.
+    pub fn find(error: &anyhow::Error) -> Option<Self> {
+        error.downcast_ref::<Self>().copied()
+    }
+}
+
+/// A call failing on the side of the provider, as opposed to a message it
+/// rejected: a timeout, a connection error or a server error.
+///
+/// Only these failures count towards opening a
+/// [`CircuitBreaker`](crate::email_client::CircuitBreaker).
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("{message}")]
+pub struct ProviderFailure {
+    message: String,
+    /// Whether the provider may have accepted the email before failing.
+    may_be_sent: bool,
+}
+
+impl ProviderFailure {
+    /// A failure after the email was handed to the provider, which may have
+    /// sent it anyway: a timeout or a server error.
+    pub fn new(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            message: format!("{:#}", error.into()),
+            may_be_sent: true,
+        }
+    }
+
+    /// A failure before the provider accepted the email, such as a connection
+    /// error: sending it again cannot duplicate it.
+    pub fn unsent(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            may_be_sent: false,
+            ..Self::new(error)
+        }
+    }
+
+    /// The same failure, described by `error`.
+    pub fn with_cause(&self, error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            may_be_sent: self.may_be_sent,
+            ..Self::new(error)
+        }
+    }
+
+    /// The failure of the email failing with `error`, if the provider failed.
+    pub fn find(error: &anyhow::Error) -> Option<&Self> {
+        error.downcast_ref::<Self>()
+    }
+
+    /// Whether the email failing with `error` failed on the side of the
+    /// provider.
+    pub fn is_cause_of(error: &anyhow::Error) -> bool {
+        Self::find(error).is_some()
+    }
+
+    /// Whether the provider may have sent the email despite the failure.
+    pub fn may_be_sent(&self) -> bool {
+        self.may_be_sent
+    }
+}
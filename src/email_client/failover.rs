@@ -0,0 +1,326 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::email_client::{
+    CircuitState,
+    Email,
+    EmailSender,
+    NotAttempted,
+    ProviderFailure,
+    RemainingQuota,
+    SendMetricsSnapshot,
+    SentEmail,
+};
+
+/// An [`EmailSender`] named after its `email_client` settings, recorded as the
+/// [`provider`](SentEmail::provider) of the emails it carries.
+pub struct EmailProvider {
+    pub name: String,
+    pub sender: Arc<dyn EmailSender>,
+}
+
+/// Send through the first of several providers, in priority order, that is
+/// available.
+///
+/// A provider whose [`CircuitBreaker`](crate::email_client::CircuitBreaker)
+/// is open is skipped, and a call none of whose emails the provider may have
+/// sent is attempted again with the next provider: every email was
+/// [`NotAttempted`] or failed with a [`ProviderFailure`] before being sent.
+/// A rejected email, or a timeout after which the provider may have sent it,
+/// is not sent again.
+pub struct FailoverSender {
+    providers: Vec<EmailProvider>,
+}
+
+impl FailoverSender {
+    /// `providers` must not be empty.
+    pub fn new(providers: Vec<EmailProvider>) -> Self {
+        assert!(
+            !providers.is_empty(),
+            "at least one email provider is required"
+        );
+        Self { providers }
+    }
+
+    /// The providers that may accept a call, in priority order.
+    fn available_providers(&self) -> impl Iterator<Item = &EmailProvider> {
+        self.providers
+            .iter()
+            .filter(|provider| provider.sender.circuit_state() != Some(CircuitState::Open))
+    }
+
+    /// The provider the next call goes to, the last one when none is
+    /// available.
+    fn next_provider(&self) -> &EmailProvider {
+        self.available_providers()
+            .next()
+            .unwrap_or_else(|| self.providers.last().unwrap())
+    }
+}
+
+#[async_trait]
+impl EmailSender for FailoverSender {
+    async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+        let mut providers = self.available_providers();
+        // with every circuit open the last provider fails the call fast
+        let mut provider = providers
+            .next()
+            .unwrap_or_else(|| self.providers.last().unwrap());
+        let mut results = provider.sender.send_emails(emails).await;
+        while !emails.is_empty() && results.iter().all(is_unsent) {
+            let next_provider = match providers.next() {
+                Some(next_provider) => next_provider,
+                None => break,
+            };
+            tracing::warn!(
+                "Email provider: {} failed, failing over to: {}",
+                provider.name,
+                next_provider.name
+            );
+            provider = next_provider;
+            results = provider.sender.send_emails(emails).await;
+        }
+        results
+            .into_iter()
+            .map(|result| {
+                result.map(|sent_email| SentEmail {
+                    provider: Some(provider.name.clone()),
+                    ..sent_email
+                })
+            })
+            .collect()
+    }
+
+    fn max_batch_size(&self) -> usize {
+        self.next_provider().sender.max_batch_size()
+    }
+
+    fn max_concurrent_requests(&self) -> usize {
+        self.next_provider().sender.max_concurrent_requests()
+    }
+
+    fn remaining_quota(&self) -> Option<RemainingQuota> {
+        self.next_provider().sender.remaining_quota()
+    }
+
+    fn send_metrics(&self) -> Vec<SendMetricsSnapshot> {
+        self.providers
+            .iter()
+            .flat_map(|provider| provider.sender.send_metrics())
+            .collect()
+    }
+
+    /// The most available state among the providers, a provider without
+    /// circuit breaker being always closed: the calls are deferred only once
+    /// every circuit is open.
+    fn circuit_state(&self) -> Option<CircuitState> {
+        let states = self
+            .providers
+            .iter()
+            .map(|provider| provider.sender.circuit_state())
+            .collect::<Vec<_>>();
+        if states.iter().all(Option::is_none) {
+            return None;
+        }
+        let states = states
+            .into_iter()
+            .map(|state| state.unwrap_or(CircuitState::Closed))
+            .collect::<Vec<_>>();
+        [
+            CircuitState::Closed,
+            CircuitState::HalfOpen,
+            CircuitState::Open,
+        ]
+        .iter()
+        .copied()
+        .find(|state| states.contains(state))
+    }
+}
+
+/// Whether `result` is the failure of an email the provider did not send, and
+/// that can be sent again without being duplicated.
+fn is_unsent(result: &Result<SentEmail, anyhow::Error>) -> bool {
+    match result {
+        Ok(_) => false,
+        Err(error) => {
+            NotAttempted::find(error).is_some()
+                || ProviderFailure::find(error).is_some_and(|failure| !failure.may_be_sent())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+    use std::time::Duration;
+
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        CircuitBreaker,
+        CircuitBreakerPolicy,
+    };
+
+    use super::*;
+
+    /// Fails every email with its `error`, if any, and counts the calls
+    /// reaching it.
+    struct StubSender {
+        error: Option<fn() -> anyhow::Error>,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl EmailSender for StubSender {
+        async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            emails
+                .iter()
+                .map(|_| match self.error {
+                    Some(error) => Err(error()),
+                    None => Ok(SentEmail::default()),
+                })
+                .collect()
+        }
+    }
+
+    fn stub_sender(error: Option<fn() -> anyhow::Error>) -> Arc<StubSender> {
+        Arc::new(StubSender {
+            error,
+            calls: AtomicUsize::new(0),
+        })
+    }
+
+    fn unreachable() -> anyhow::Error {
+        ProviderFailure::unsent(anyhow::anyhow!("connection refused")).into()
+    }
+
+    fn timed_out() -> anyhow::Error {
+        ProviderFailure::new(anyhow::anyhow!("operation timed out")).into()
+    }
+
+    fn rejected() -> anyhow::Error {
+        anyhow::anyhow!("invalid recipient")
+    }
+
+    fn quota_exhausted() -> anyhow::Error {
+        NotAttempted::QuotaExhausted(Duration::from_secs(60)).into()
+    }
+
+    fn provider(name: &str, sender: Arc<dyn EmailSender>) -> EmailProvider {
+        EmailProvider {
+            name: name.to_string(),
+            sender,
+        }
+    }
+
+    fn email() -> Email<'static> {
+        let recipient = SubscriberEmail::try_from(SafeEmail().fake::<String>()).unwrap();
+        Email::new(recipient, "any_subject", "any_html", "any_text")
+    }
+
+    #[tokio::test]
+    async fn emails_are_sent_by_the_primary_provider_and_record_it() {
+        let primary = stub_sender(None);
+        let secondary = stub_sender(None);
+        let failover_sender = FailoverSender::new(vec![
+            provider("primary", primary.clone()),
+            provider("secondary", secondary.clone()),
+        ]);
+
+        let sent_email = failover_sender.send_email(email()).await.unwrap();
+
+        assert_eq!(sent_email.provider.as_deref(), Some("primary"));
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(secondary.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn a_failed_call_is_attempted_again_with_the_next_provider() {
+        let primary = stub_sender(Some(unreachable));
+        let secondary = stub_sender(None);
+        let failover_sender = FailoverSender::new(vec![
+            provider("primary", primary.clone()),
+            provider("secondary", secondary.clone()),
+        ]);
+
+        let results = failover_sender.send_emails(&[email(), email()]).await;
+
+        assert!(results
+            .iter()
+            .all(|result| result.as_ref().unwrap().provider.as_deref() == Some("secondary")));
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn a_call_that_was_not_attempted_is_attempted_with_the_next_provider() {
+        let failover_sender = FailoverSender::new(vec![
+            provider("primary", stub_sender(Some(quota_exhausted))),
+            provider("secondary", stub_sender(None)),
+        ]);
+
+        let sent_email = failover_sender.send_email(email()).await.unwrap();
+
+        assert_eq!(sent_email.provider.as_deref(), Some("secondary"));
+    }
+
+    #[tokio::test]
+    async fn emails_the_provider_may_have_sent_or_rejected_are_not_sent_again() {
+        for error in [timed_out as fn() -> anyhow::Error, rejected].iter() {
+            let secondary = stub_sender(None);
+            let failover_sender = FailoverSender::new(vec![
+                provider("primary", stub_sender(Some(*error))),
+                provider("secondary", secondary.clone()),
+            ]);
+
+            let results = failover_sender.send_emails(&[email(), email()]).await;
+
+            assert!(results.iter().all(Result::is_err));
+            assert_eq!(secondary.calls.load(Ordering::SeqCst), 0, "{}", error());
+        }
+    }
+
+    #[tokio::test]
+    async fn the_error_of_the_last_provider_is_returned_when_every_provider_fails() {
+        let failover_sender = FailoverSender::new(vec![
+            provider("primary", stub_sender(Some(unreachable))),
+            provider("secondary", stub_sender(Some(unreachable))),
+        ]);
+
+        assert!(failover_sender.send_email(email()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_provider_with_an_open_circuit_is_skipped() {
+        let primary = stub_sender(Some(unreachable));
+        let secondary = stub_sender(None);
+        let primary_breaker = Arc::new(CircuitBreaker::new(
+            primary.clone(),
+            CircuitBreakerPolicy {
+                failure_threshold: 1,
+                open_duration: Duration::from_secs(60),
+            },
+        ));
+        let failover_sender = FailoverSender::new(vec![
+            provider("primary", primary_breaker),
+            provider("secondary", secondary.clone()),
+        ]);
+
+        for _ in 0..3 {
+            let sent_email = failover_sender.send_email(email()).await.unwrap();
+            assert_eq!(sent_email.provider.as_deref(), Some("secondary"));
+        }
+
+        // the first failure opened the circuit of the primary provider
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(secondary.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(failover_sender.circuit_state(), Some(CircuitState::Closed));
+    }
+}
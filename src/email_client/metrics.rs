@@ -0,0 +1,350 @@
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+use std::time::Duration;
+
+use hmac::{
+    Hmac,
+    Mac,
+    NewMac,
+};
+use reqwest::StatusCode;
+use sha2::Sha256;
+
+use crate::email_client::RemainingQuota;
+
+/// The upper bounds, in seconds, of the buckets of the latency histograms.
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// How a call to the email provider ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SendOutcome {
+    Success,
+    ClientError,
+    ServerError,
+    Timeout,
+    /// The provider could not be reached, or answered with an unexpected
+    /// status.
+    Error,
+}
+
+impl SendOutcome {
+    pub const ALL: [SendOutcome; 5] = [
+        SendOutcome::Success,
+        SendOutcome::ClientError,
+        SendOutcome::ServerError,
+        SendOutcome::Timeout,
+        SendOutcome::Error,
+    ];
+
+    pub fn from_status(status: StatusCode) -> Self {
+        if status.is_success() {
+            SendOutcome::Success
+        } else if status.is_client_error() {
+            SendOutcome::ClientError
+        } else if status.is_server_error() {
+            SendOutcome::ServerError
+        } else {
+            SendOutcome::Error
+        }
+    }
+
+    /// The `outcome` label of the metrics and of the tracing spans.
+    pub fn label(self) -> &'static str {
+        match self {
+            SendOutcome::Success => "success",
+            SendOutcome::ClientError => "4xx",
+            SendOutcome::ServerError => "5xx",
+            SendOutcome::Timeout => "timeout",
+            SendOutcome::Error => "error",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|outcome| *outcome == self)
+            .unwrap()
+    }
+}
+
+/// The number and the latency of the calls to an email provider, by
+/// [`SendOutcome`].
+#[derive(Debug, Default)]
+pub struct SendMetrics {
+    outcomes: [OutcomeMetrics; 5],
+}
+
+#[derive(Debug, Default)]
+struct OutcomeMetrics {
+    calls: AtomicU64,
+    /// The calls completed within each of the [`LATENCY_BUCKETS`], not
+    /// cumulated.
+    latency_buckets: [AtomicU64; 11],
+    latency_sum_micros: AtomicU64,
+}
+
+/// The [`SendMetrics`] of a provider for one [`SendOutcome`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SendMetricsSnapshot {
+    pub provider: String,
+    pub outcome: SendOutcome,
+    pub calls: u64,
+    /// The calls completed within each of the [`LATENCY_BUCKETS`], cumulated
+    /// as in a Prometheus histogram.
+    pub latency_buckets: Vec<u64>,
+    pub latency_sum: Duration,
+}
+
+impl SendMetrics {
+    pub fn record(&self, outcome: SendOutcome, latency: Duration) {
+        let metrics = &self.outcomes[outcome.index()];
+        metrics.calls.fetch_add(1, Ordering::Relaxed);
+        if let Some(bucket) = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency.as_secs_f64() <= *bound)
+        {
+            metrics.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        metrics
+            .latency_sum_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// The metrics of every outcome, those without calls included.
+    pub fn snapshot(&self, provider: &str) -> Vec<SendMetricsSnapshot> {
+        SendOutcome::ALL
+            .iter()
+            .map(|outcome| {
+                let metrics = &self.outcomes[outcome.index()];
+                let latency_buckets = metrics
+                    .latency_buckets
+                    .iter()
+                    .scan(0, |calls, bucket| {
+                        *calls += bucket.load(Ordering::Relaxed);
+                        Some(*calls)
+                    })
+                    .collect();
+                SendMetricsSnapshot {
+                    provider: provider.to_string(),
+                    outcome: *outcome,
+                    calls: metrics.calls.load(Ordering::Relaxed),
+                    latency_buckets,
+                    latency_sum: Duration::from_micros(
+                        metrics.latency_sum_micros.load(Ordering::Relaxed),
+                    ),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Format `metrics` in the Prometheus text exposition format.
+pub fn render_prometheus(metrics: &[SendMetricsSnapshot]) -> String {
+    let labels = |metric: &SendMetricsSnapshot| {
+        format!(
+            "provider=\"{}\",outcome=\"{}\"",
+            metric.provider.replace('\\', "\\\\").replace('"', "\\\""),
+            metric.outcome.label()
+        )
+    };
+    let mut lines = vec![
+        "# HELP email_sends_total The calls to the email providers.".to_string(),
+        "# TYPE email_sends_total counter".to_string(),
+    ];
+    for metric in metrics {
+        lines.push(format!(
+            "email_sends_total{{{}}} {}",
+            labels(metric),
+            metric.calls
+        ));
+    }
+    lines.push(
+        "# HELP email_send_duration_seconds The latency of the calls to the email providers."
+            .to_string(),
+    );
+    lines.push("# TYPE email_send_duration_seconds histogram".to_string());
+    for metric in metrics {
+        for (bound, calls) in LATENCY_BUCKETS.iter().zip(&metric.latency_buckets) {
+            lines.push(format!(
+                "email_send_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                labels(metric),
+                bound,
+                calls
+            ));
+        }
+        lines.push(format!(
+            "email_send_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+            labels(metric),
+            metric.calls
+        ));
+        lines.push(format!(
+            "email_send_duration_seconds_sum{{{}}} {}",
+            labels(metric),
+            metric.latency_sum.as_secs_f64()
+        ));
+        lines.push(format!(
+            "email_send_duration_seconds_count{{{}}} {}",
+            labels(metric),
+            metric.calls
+        ));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Format the [`RemainingQuota`] of a transport as Prometheus gauges, nothing
+/// for the transports without quota.
+pub fn render_remaining_quota(quota: Option<RemainingQuota>) -> String {
+    let quota = match quota {
+        Some(quota) => quota,
+        None => return String::new(),
+    };
+    let mut lines = vec![
+        "# HELP email_remaining_quota The messages that can be sent without waiting.".to_string(),
+        "# TYPE email_remaining_quota gauge".to_string(),
+        format!("email_remaining_quota{{window=\"burst\"}} {}", quota.burst),
+    ];
+    if let Some(day) = quota.day {
+        lines.push(format!("email_remaining_quota{{window=\"day\"}} {}", day));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Identify a recipient in the logs without disclosing their address.
+///
+/// The hash is an HMAC keyed by `key`: without it, the addresses cannot be
+/// recovered by hashing candidate ones.
+pub fn recipient_hash(key: &[u8], recipient: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC accepts keys of any size");
+    mac.update(b"recipient:");
+    mac.update(recipient.to_lowercase().as_bytes());
+    let hash = format!("{:x}", mac.finalize().into_bytes());
+    hash[..16].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls_are_counted_by_outcome_with_cumulated_latency_buckets() {
+        let metrics = SendMetrics::default();
+
+        metrics.record(SendOutcome::Success, Duration::from_millis(20));
+        metrics.record(SendOutcome::Success, Duration::from_millis(300));
+        metrics.record(SendOutcome::Success, Duration::from_secs(30));
+        metrics.record(SendOutcome::Timeout, Duration::from_secs(10));
+
+        let snapshot = metrics.snapshot("mailjet");
+        let success = &snapshot[0];
+        assert_eq!(success.outcome, SendOutcome::Success);
+        assert_eq!(success.calls, 3);
+        assert_eq!(
+            success.latency_buckets,
+            vec![0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2]
+        );
+        assert_eq!(success.latency_sum, Duration::from_millis(30_320));
+        let timeout = &snapshot[3];
+        assert_eq!(timeout.outcome, SendOutcome::Timeout);
+        assert_eq!(timeout.calls, 1);
+        assert_eq!(*timeout.latency_buckets.last().unwrap(), 1);
+        assert!(snapshot
+            .iter()
+            .filter(|metric| metric.outcome != SendOutcome::Success
+                && metric.outcome != SendOutcome::Timeout)
+            .all(|metric| metric.calls == 0));
+    }
+
+    #[test]
+    fn statuses_are_mapped_to_outcomes() {
+        assert_eq!(
+            SendOutcome::from_status(StatusCode::OK),
+            SendOutcome::Success
+        );
+        assert_eq!(
+            SendOutcome::from_status(StatusCode::TOO_MANY_REQUESTS),
+            SendOutcome::ClientError
+        );
+        assert_eq!(
+            SendOutcome::from_status(StatusCode::BAD_GATEWAY),
+            SendOutcome::ServerError
+        );
+        assert_eq!(
+            SendOutcome::from_status(StatusCode::MOVED_PERMANENTLY),
+            SendOutcome::Error
+        );
+    }
+
+    #[test]
+    fn metrics_are_rendered_in_the_prometheus_format() {
+        let metrics = SendMetrics::default();
+        metrics.record(SendOutcome::ServerError, Duration::from_millis(200));
+
+        let rendered = render_prometheus(&metrics.snapshot("mailjet"));
+
+        let labels = "provider=\"mailjet\",outcome=\"5xx\"";
+        assert!(rendered.contains("# TYPE email_sends_total counter\n"));
+        assert!(rendered.contains(&format!("email_sends_total{{{}}} 1\n", labels)));
+        assert!(
+            rendered.contains("email_sends_total{provider=\"mailjet\",outcome=\"success\"} 0\n")
+        );
+        assert!(rendered.contains("# TYPE email_send_duration_seconds histogram\n"));
+        assert!(rendered.contains(&format!(
+            "email_send_duration_seconds_bucket{{{},le=\"0.1\"}} 0\n",
+            labels
+        )));
+        assert!(rendered.contains(&format!(
+            "email_send_duration_seconds_bucket{{{},le=\"0.25\"}} 1\n",
+            labels
+        )));
+        assert!(rendered.contains(&format!(
+            "email_send_duration_seconds_bucket{{{},le=\"+Inf\"}} 1\n",
+            labels
+        )));
+        assert!(rendered.contains(&format!(
+            "email_send_duration_seconds_sum{{{}}} 0.2\n",
+            labels
+        )));
+        assert!(rendered.contains(&format!(
+            "email_send_duration_seconds_count{{{}}} 1\n",
+            labels
+        )));
+    }
+
+    #[test]
+    fn the_remaining_quota_is_rendered_as_gauges() {
+        let rendered = render_remaining_quota(Some(RemainingQuota {
+            burst: 7,
+            day: Some(120),
+        }));
+
+        assert!(rendered.contains("# TYPE email_remaining_quota gauge\n"));
+        assert!(rendered.contains("email_remaining_quota{window=\"burst\"} 7\n"));
+        assert!(rendered.contains("email_remaining_quota{window=\"day\"} 120\n"));
+        assert_eq!(render_remaining_quota(None), "");
+    }
+
+    #[test]
+    fn recipients_are_hashed_case_insensitively() {
+        let hash = recipient_hash(b"any_key", "Ursula@example.com");
+
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, recipient_hash(b"any_key", "ursula@example.com"));
+        assert_ne!(hash, recipient_hash(b"any_key", "ursula@example.org"));
+        assert!(!hash.contains("ursula"));
+    }
+
+    #[test]
+    fn recipients_are_hashed_with_the_key() {
+        let hash = recipient_hash(b"any_key", "ursula@example.com");
+
+        assert_ne!(hash, recipient_hash(b"other_key", "ursula@example.com"));
+        // the truncated SHA-256 of the address
+        assert_ne!(hash, "00b41d24b65242f8");
+    }
+}
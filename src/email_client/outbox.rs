@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    Email,
+    EmailSender,
+    SentEmail,
+    DEFAULT_SENDER_NAME,
+};
+
+/// Write every email to a directory instead of sending it, for development
+/// and CI.
+#[derive(Debug)]
+pub struct OutboxClient {
+    directory: PathBuf,
+    sender: SubscriberEmail,
+    sender_name: String,
+    reply_to: Option<SubscriberEmail>,
+}
+
+/// The JSON record written for each email.
+#[derive(Serialize)]
+struct OutboxRecord<'a> {
+    from: &'a str,
+    from_name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    to: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_name: Option<&'a str>,
+    subject: &'a str,
+    text_part: &'a str,
+    html_part: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<OutboxAttachment<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<&'a str, &'a str>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct OutboxAttachment<'a> {
+    filename: &'a str,
+    content_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_id: Option<&'a str>,
+    /// Encoded in base64.
+    content: String,
+}
+
+impl OutboxClient {
+    /// Create `directory` if it does not exist yet.
+    pub fn new(directory: PathBuf, sender: SubscriberEmail) -> Result<Self, anyhow::Error> {
+        std::fs::create_dir_all(&directory)
+            .context(format!("Error creating outbox directory: {:?}", directory))?;
+        Ok(Self {
+            directory,
+            sender,
+            sender_name: DEFAULT_SENDER_NAME.to_string(),
+            reply_to: None,
+        })
+    }
+
+    /// Send the emails under `sender_name` instead of the
+    /// [default](DEFAULT_SENDER_NAME) one.
+    pub fn with_sender_name(mut self, sender_name: String) -> Self {
+        self.sender_name = sender_name;
+        self
+    }
+
+    /// Direct the replies of the recipients to `reply_to` instead of the
+    /// sender.
+    pub fn with_reply_to(mut self, reply_to: SubscriberEmail) -> Self {
+        self.reply_to = Some(reply_to);
+        self
+    }
+
+    /// Files are named after their creation time, so that listing the
+    /// directory in order lists the emails in the order they were sent.
+    ///
+    /// The file name, without its extension, is returned as the
+    /// [`message_id`](SentEmail::message_id).
+    async fn write(&self, email: &Email<'_>) -> Result<SentEmail, anyhow::Error> {
+        let created_at = Utc::now();
+        let record = OutboxRecord {
+            from: self.sender.as_ref(),
+            from_name: &self.sender_name,
+            reply_to: self.reply_to.as_ref().map(AsRef::as_ref),
+            to: email.recipient.as_ref(),
+            to_name: email.recipient_name,
+            subject: email.subject,
+            text_part: email.text_part,
+            html_part: email.html_part,
+            attachments: email
+                .attachments
+                .iter()
+                .map(|attachment| OutboxAttachment {
+                    filename: attachment.filename(),
+                    content_type: attachment.content_type(),
+                    content_id: attachment.content_id(),
+                    content: base64::encode(attachment.content()),
+                })
+                .collect(),
+            custom_id: email.custom_id.as_deref(),
+            headers: email
+                .headers
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect(),
+            created_at,
+        };
+        let message_id = format!(
+            "{}-{}",
+            created_at.format("%Y%m%dT%H%M%S%.6fZ"),
+            Uuid::new_v4()
+        );
+        let file_name = format!("{}.json", message_id);
+        // the record is renamed once complete, so that readers of the outbox
+        // never see a partially written file
+        let partial_path = self.directory.join(format!(".{}.partial", file_name));
+        tokio::fs::write(&partial_path, serde_json::to_vec_pretty(&record)?)
+            .await
+            .context(format!("Error writing email to: {:?}", partial_path))?;
+        tokio::fs::rename(&partial_path, self.directory.join(&file_name))
+            .await
+            .context(format!("Error moving email to the outbox: {}", file_name))?;
+        Ok(SentEmail {
+            message_id: Some(message_id),
+            ..SentEmail::default()
+        })
+    }
+}
+
+#[async_trait]
+impl EmailSender for OutboxClient {
+    async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            results.push(self.write(email).await.with_context(|| {
+                format!(
+                    "Error sending email to: {} with subject: {}",
+                    email.recipient.as_ref(),
+                    email.subject
+                )
+            }));
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::path::Path;
+
+    use claim::assert_ok;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+    use serde_json::Value;
+
+    use crate::domain::EmailAttachment;
+
+    use super::*;
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::try_from(SafeEmail().fake::<String>()).unwrap()
+    }
+
+    fn outbox_directory() -> PathBuf {
+        std::env::temp_dir().join(format!("outbox-{}", Uuid::new_v4()))
+    }
+
+    fn read_outbox(directory: &Path) -> Vec<Value> {
+        let mut paths = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_a_record_to_the_outbox() {
+        let directory = outbox_directory();
+        let sender = email();
+        let recipient = email();
+        let outbox_client = OutboxClient::new(directory.clone(), sender.clone()).unwrap();
+
+        let outcome = outbox_client
+            .send_email(Email::new(
+                recipient.clone(),
+                "any_subject",
+                "any_html",
+                "any_text",
+            ))
+            .await;
+
+        let message_id = assert_ok!(outcome).message_id.unwrap();
+        assert!(directory.join(format!("{}.json", message_id)).exists());
+        let records = read_outbox(&directory);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["from"], sender.as_ref());
+        assert_eq!(records[0]["to"], recipient.as_ref());
+        assert_eq!(records[0]["subject"], "any_subject");
+        assert_eq!(records[0]["html_part"], "any_html");
+        assert_eq!(records[0]["text_part"], "any_text");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_the_sender_identity_recipient_name_and_headers() {
+        let directory = outbox_directory();
+        let reply_to = email();
+        let outbox_client = OutboxClient::new(directory.clone(), email())
+            .unwrap()
+            .with_sender_name("Weekly Digest".to_string())
+            .with_reply_to(reply_to.clone());
+
+        let outcome = outbox_client
+            .send_email(Email {
+                recipient_name: Some("Ursula Le Guin"),
+                custom_id: Some("issue:subscriber".to_string()),
+                headers: vec![("List-Unsubscribe", "<https://any_url>".to_string())],
+                ..Email::new(email(), "any_subject", "any_html", "any_text")
+            })
+            .await;
+
+        assert_ok!(outcome);
+        let records = read_outbox(&directory);
+        assert_eq!(records[0]["from_name"], "Weekly Digest");
+        assert_eq!(records[0]["reply_to"], reply_to.as_ref());
+        assert_eq!(records[0]["to_name"], "Ursula Le Guin");
+        assert_eq!(records[0]["custom_id"], "issue:subscriber");
+        assert_eq!(
+            records[0]["headers"],
+            serde_json::json!({ "List-Unsubscribe": "<https://any_url>" })
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_emails_writes_the_attachments_in_the_record() {
+        let directory = outbox_directory();
+        let outbox_client = OutboxClient::new(directory.clone(), email()).unwrap();
+        let attachments = [
+            EmailAttachment::parse(
+                "report.pdf".to_string(),
+                "application/pdf".to_string(),
+                b"any_pdf".to_vec(),
+                None,
+            )
+            .unwrap(),
+            EmailAttachment::parse(
+                "logo.png".to_string(),
+                "image/png".to_string(),
+                b"any_png".to_vec(),
+                Some("logo".to_string()),
+            )
+            .unwrap(),
+        ];
+        let email = Email {
+            attachments: &attachments,
+            ..Email::new(email(), "any_subject", "<img src=\"cid:logo\">", "any_text")
+        };
+
+        let results = outbox_client
+            .send_emails(std::slice::from_ref(&email))
+            .await;
+
+        assert!(results.iter().all(Result::is_ok));
+        let records = read_outbox(&directory);
+        assert_eq!(
+            records[0]["attachments"],
+            serde_json::json!([
+                {
+                    "filename": "report.pdf",
+                    "content_type": "application/pdf",
+                    "content": base64::encode("any_pdf"),
+                },
+                {
+                    "filename": "logo.png",
+                    "content_type": "image/png",
+                    "content_id": "logo",
+                    "content": base64::encode("any_png"),
+                },
+            ])
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_emails_writes_one_record_for_each_email_in_order() {
+        let directory = outbox_directory();
+        let outbox_client = OutboxClient::new(directory.clone(), email()).unwrap();
+        let subjects = (0..3).map(|i| format!("subject_{}", i)).collect::<Vec<_>>();
+        let emails = subjects
+            .iter()
+            .map(|subject| Email::new(email(), subject, "any_html", "any_text"))
+            .collect::<Vec<_>>();
+
+        let results = outbox_client.send_emails(&emails).await;
+
+        assert!(results.iter().all(Result::is_ok));
+        let records = read_outbox(&directory);
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record["subject"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            subjects
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use std::sync::Mutex;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use serde::Serialize;
+
+use crate::email_client::NotAttempted;
+
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// The sending rate allowed by the plan of the email provider.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    /// The sustained number of messages sent each second.
+    pub messages_per_second: f64,
+    /// The number of messages that can be sent at once after a quiet period.
+    pub burst: u32,
+    /// The daily quota of the plan, if any.
+    pub messages_per_day: Option<u32>,
+    /// The longest a send waits for the quota to be refilled before failing.
+    pub max_wait: Duration,
+}
+
+/// The number of messages that can be sent right now without waiting.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct RemainingQuota {
+    pub burst: u32,
+    /// Set when the [`RateLimit`] has a daily quota.
+    pub day: Option<u32>,
+}
+
+/// A token bucket per limit of a [`RateLimit`], shared by every caller of the
+/// client owning it.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_wait: Duration,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    burst: TokenBucket,
+    day: Option<TokenBucket>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Start with full buckets.
+    pub fn new(rate_limit: RateLimit) -> Self {
+        let now = Instant::now();
+        Self {
+            max_wait: rate_limit.max_wait,
+            buckets: Mutex::new(Buckets {
+                burst: TokenBucket::full(
+                    f64::from(rate_limit.burst.max(1)),
+                    rate_limit.messages_per_second,
+                    now,
+                ),
+                day: rate_limit.messages_per_day.map(|messages_per_day| {
+                    TokenBucket::full(
+                        f64::from(messages_per_day),
+                        f64::from(messages_per_day) / SECONDS_PER_DAY,
+                        now,
+                    )
+                }),
+            }),
+        }
+    }
+
+    /// Take `messages` from every bucket, waiting for them to be refilled if
+    /// needed.
+    ///
+    /// It fails with [`NotAttempted::QuotaExhausted`], without taking
+    /// anything, when the quota cannot be refilled within `max_wait`.
+    pub async fn acquire(&self, messages: usize) -> Result<(), anyhow::Error> {
+        let messages = messages as f64;
+        let deadline = Instant::now() + self.max_wait;
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let now = Instant::now();
+                let wait = buckets
+                    .iter_mut()
+                    .map(|bucket| {
+                        bucket.refill(now);
+                        bucket.wait_for(messages)
+                    })
+                    .max()
+                    .unwrap_or_default();
+                if wait == Duration::from_secs(0) {
+                    buckets.iter_mut().for_each(|bucket| bucket.take(messages));
+                    return Ok(());
+                }
+                if wait > deadline.saturating_duration_since(now) {
+                    return Err(NotAttempted::QuotaExhausted(wait).into());
+                }
+                wait
+            };
+            // other callers may take the refilled tokens first, so the wait
+            // is computed again afterwards
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    pub fn remaining_quota(&self) -> RemainingQuota {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        buckets.iter_mut().for_each(|bucket| bucket.refill(now));
+        RemainingQuota {
+            burst: buckets.burst.remaining(),
+            day: buckets.day.as_ref().map(TokenBucket::remaining),
+        }
+    }
+}
+
+impl Buckets {
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut TokenBucket> {
+        std::iter::once(&mut self.burst).chain(self.day.as_mut())
+    }
+}
+
+impl TokenBucket {
+    fn full(capacity: f64, refill_per_second: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_second,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.refill_per_second).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// A batch larger than the bucket only waits for a full bucket, and then
+    /// borrows on the next refills, rather than never being sent.
+    fn wait_for(&self, messages: f64) -> Duration {
+        let missing = messages.min(self.capacity) - self.tokens;
+        if missing <= 0.0 {
+            Duration::from_secs(0)
+        } else if self.refill_per_second <= 0.0 {
+            Duration::MAX
+        } else {
+            Duration::from_secs_f64(missing / self.refill_per_second)
+        }
+    }
+
+    fn take(&mut self, messages: f64) {
+        self.tokens -= messages;
+    }
+
+    fn remaining(&self) -> u32 {
+        self.tokens.max(0.0).floor() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    use claim::{
+        assert_err,
+        assert_ok,
+    };
+
+    use super::*;
+
+    fn rate_limit(messages_per_second: f64, burst: u32) -> RateLimit {
+        RateLimit {
+            messages_per_second,
+            burst,
+            messages_per_day: None,
+            max_wait: Duration::from_secs(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_full_bucket_sends_a_burst_without_waiting() {
+        let rate_limiter = RateLimiter::new(rate_limit(1.0, 10));
+        let start = Instant::now();
+
+        for _ in 0..10 {
+            assert_ok!(rate_limiter.acquire(1).await);
+        }
+
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(rate_limiter.remaining_quota().burst, 0);
+    }
+
+    #[tokio::test]
+    async fn an_empty_bucket_waits_for_the_refill() {
+        let rate_limiter = RateLimiter::new(rate_limit(20.0, 2));
+        assert_ok!(rate_limiter.acquire(2).await);
+        let start = Instant::now();
+
+        assert_ok!(rate_limiter.acquire(2).await);
+
+        // 2 messages at 20 per second
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn a_batch_larger_than_the_bucket_is_sent_once_the_bucket_is_full() {
+        let rate_limiter = RateLimiter::new(rate_limit(1.0, 5));
+
+        assert_ok!(rate_limiter.acquire(8).await);
+
+        assert_eq!(rate_limiter.remaining_quota().burst, 0);
+    }
+
+    #[tokio::test]
+    async fn an_exhausted_daily_quota_fails_instead_of_waiting() {
+        let rate_limiter = RateLimiter::new(RateLimit {
+            messages_per_day: Some(3),
+            ..rate_limit(0.001, 100)
+        });
+        assert_ok!(rate_limiter.acquire(3).await);
+        assert_eq!(
+            rate_limiter.remaining_quota(),
+            RemainingQuota {
+                burst: 97,
+                day: Some(0)
+            }
+        );
+
+        assert_err!(rate_limiter.acquire(1).await);
+        // the failed attempt takes nothing
+        assert_eq!(rate_limiter.remaining_quota().burst, 97);
+    }
+}
@@ -1,4 +1,12 @@
-use serde::Serialize;
+use std::collections::BTreeMap;
+
+use serde::{
+    Serialize,
+    Serializer,
+};
+
+use crate::domain::EmailAttachment;
+use crate::email_client::Email;
 
 #[derive(Serialize)]
 #[serde(rename_all = "PascalCase")]
@@ -16,8 +24,17 @@ pub struct Message<'a> {
     pub text_part: &'a str,
     #[serde(rename = "HTMLPart")]
     pub html_part: &'a str,
-    #[serde(rename = "CustomID")]
-    pub custom_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<ReplyTo<'a>>,
+    #[serde(rename = "CustomID", skip_serializing_if = "Option::is_none")]
+    pub custom_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<&'a str, &'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment<'a>>,
+    /// Images displayed by the HTML part, referenced as `cid:<ContentID>`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inlined_attachments: Vec<Attachment<'a>>,
 }
 
 #[derive(Serialize)]
@@ -30,35 +47,90 @@ pub struct From<'a> {
 #[derive(Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct To<'a> {
+    pub email: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReplyTo<'a> {
+    pub email: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Attachment<'a> {
+    pub content_type: &'a str,
+    pub filename: &'a str,
+    #[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+    pub content_id: Option<&'a str>,
+    #[serde(serialize_with = "serialize_base64")]
+    pub base64_content: &'a [u8],
+}
+
+fn serialize_base64<S: Serializer>(content: &&[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::encode(content))
+}
+
+impl<'a> Attachment<'a> {
+    fn new(attachment: &'a EmailAttachment) -> Self {
+        Self {
+            content_type: attachment.content_type(),
+            filename: attachment.filename(),
+            content_id: attachment.content_id(),
+            base64_content: attachment.content(),
+        }
+    }
+}
+
+/// The identity every email of an [`EmailRequest`] is sent from.
+#[derive(Clone, Copy)]
+pub struct Sender<'a> {
     pub email: &'a str,
     pub name: &'a str,
+    pub reply_to: Option<&'a str>,
 }
 
 impl<'a> EmailRequest<'a> {
-    const MAIL_NAME: &'a str = "Newsletter";
-
-    pub fn new(
-        sender: &'a str,
-        recipient: &'a str,
-        subject: &'a str,
-        html_part: &'a str,
-        text_part: &'a str,
-    ) -> Self {
+    /// Pack every email in a single request.
+    pub fn from_emails(sender: Sender<'a>, emails: &'a [Email<'a>]) -> Self {
         Self {
-            messages: vec![Message {
-                from: From {
-                    email: sender,
-                    name: Self::MAIL_NAME,
-                },
-                to: vec![To {
-                    email: recipient,
-                    name: Self::MAIL_NAME,
-                }],
-                subject,
-                text_part,
-                html_part,
-                custom_id: Self::MAIL_NAME,
-            }],
+            messages: emails
+                .iter()
+                .map(|email| Message {
+                    from: From {
+                        email: sender.email,
+                        name: sender.name,
+                    },
+                    to: vec![To {
+                        email: email.recipient.as_ref(),
+                        name: email.recipient_name,
+                    }],
+                    subject: email.subject,
+                    text_part: email.text_part,
+                    html_part: email.html_part,
+                    reply_to: sender.reply_to.map(|email| ReplyTo { email }),
+                    custom_id: email.custom_id.as_deref(),
+                    headers: email
+                        .headers
+                        .iter()
+                        .map(|(name, value)| (*name, value.as_str()))
+                        .collect(),
+                    attachments: email
+                        .attachments
+                        .iter()
+                        .filter(|attachment| !attachment.is_inline())
+                        .map(Attachment::new)
+                        .collect(),
+                    inlined_attachments: email
+                        .attachments
+                        .iter()
+                        .filter(|attachment| attachment.is_inline())
+                        .map(Attachment::new)
+                        .collect(),
+                })
+                .collect(),
         }
     }
 }
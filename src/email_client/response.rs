@@ -0,0 +1,61 @@
+use serde::Deserialize;
+
+use crate::email_client::SentEmail;
+
+/// The body returned by the `/send` endpoint: one result per message, in the
+/// order they were sent.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailResponse {
+    pub messages: Vec<MessageResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageResult {
+    pub status: String,
+    #[serde(default)]
+    pub errors: Vec<MessageError>,
+    #[serde(default)]
+    pub to: Vec<MessageRecipient>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageRecipient {
+    #[serde(rename = "MessageID")]
+    pub message_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageError {
+    pub error_message: String,
+    #[serde(default)]
+    pub error_code: String,
+}
+
+impl MessageResult {
+    pub fn into_result(self) -> Result<SentEmail, anyhow::Error> {
+        if self.status == "success" {
+            // every message has a single recipient
+            return Ok(SentEmail {
+                message_id: self
+                    .to
+                    .first()
+                    .map(|recipient| recipient.message_id.to_string()),
+                ..SentEmail::default()
+            });
+        }
+        let errors = self
+            .errors
+            .iter()
+            .map(|e| format!("{} ({})", e.error_message, e.error_code))
+            .collect::<Vec<_>>();
+        Err(anyhow::anyhow!(
+            "Message rejected with status: {}: {}",
+            self.status,
+            errors.join("; ")
+        ))
+    }
+}
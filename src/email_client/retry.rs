@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// How many times, and how far apart, a failed send is attempted again.
+///
+/// The delay before attempt `n + 1` is `base_delay * 2^(n - 1)`, capped at
+/// `max_delay`, with a random `jitter` fraction of it removed so that many
+/// clients failing together do not retry in lockstep.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// A policy that gives up on the first failure.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(0),
+            max_delay: Duration::from_millis(0),
+            jitter: 0.0,
+        }
+    }
+
+    /// The delay to wait after the failed attempt number `attempt` (starting at
+    /// 1).
+    pub fn delay_after(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter > 0.0 {
+            delay.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>())
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    fn policy_with_jitter(jitter: f64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max_delay() {
+        let policy = policy_with_jitter(0.0);
+        assert_eq!(policy.delay_after(1), Duration::from_millis(100));
+        assert_eq!(policy.delay_after(2), Duration::from_millis(200));
+        assert_eq!(policy.delay_after(3), Duration::from_millis(400));
+        assert_eq!(policy.delay_after(4), Duration::from_millis(800));
+        assert_eq!(policy.delay_after(5), Duration::from_millis(1000));
+        assert_eq!(policy.delay_after(u32::MAX), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let policy = policy_with_jitter(0.5);
+        for attempt in 1..10 {
+            let delay = policy.delay_after(attempt);
+            let expected = policy_with_jitter(0.0).delay_after(attempt);
+            assert!(delay <= expected);
+            assert!(delay >= expected / 2);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+
+use crate::email_client::{
+    CircuitState,
+    Email,
+    RemainingQuota,
+    SendMetricsSnapshot,
+    SentEmail,
+};
+
+/// A transport able to deliver emails, chosen with the `email_client.backend`
+/// setting.
+///
+/// The routes and the delivery workers only depend on this trait, so any
+/// transport can be plugged into
+/// [`NewsletterApp::with_email_sender`](crate::app::NewsletterApp::with_email_sender).
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    /// Send `emails`, returning one result for each email in the same order,
+    /// so that a rejected message does not fail the others.
+    async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>>;
+
+    /// The number of messages packed in each call made by
+    /// [`send_emails`](EmailSender::send_emails).
+    fn max_batch_size(&self) -> usize {
+        1
+    }
+
+    /// The number of calls [`send_emails`](EmailSender::send_emails) keeps in
+    /// flight.
+    fn max_concurrent_requests(&self) -> usize {
+        1
+    }
+
+    /// The messages that can be sent right now, for the transports enforcing
+    /// a [`RateLimit`](crate::email_client::RateLimit).
+    fn remaining_quota(&self) -> Option<RemainingQuota> {
+        None
+    }
+
+    /// The state of the [`CircuitBreaker`](crate::email_client::CircuitBreaker)
+    /// guarding the transport, if any.
+    fn circuit_state(&self) -> Option<CircuitState> {
+        None
+    }
+
+    /// The [`SendMetrics`](crate::email_client::SendMetrics) of the
+    /// providers behind the transport, for those recording them.
+    fn send_metrics(&self) -> Vec<SendMetricsSnapshot> {
+        Vec::new()
+    }
+
+    async fn send_email(&self, email: Email<'_>) -> Result<SentEmail, anyhow::Error> {
+        self.send_emails(std::slice::from_ref(&email))
+            .await
+            .pop()
+            .expect("one result for each email")
+    }
+}
@@ -0,0 +1,697 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use derivative::Derivative;
+use futures::{
+    stream,
+    StreamExt,
+};
+use lettre::message::header::{
+    ContentType,
+    HeaderName,
+    HeaderValue,
+};
+use lettre::message::{
+    Attachment,
+    Mailbox,
+    MultiPart,
+    SinglePart,
+};
+use lettre::transport::smtp::authentication::{
+    Credentials,
+    Mechanism,
+};
+use lettre::transport::smtp::client::{
+    Tls,
+    TlsParameters,
+};
+use lettre::transport::smtp::PoolConfig;
+use lettre::{
+    AsyncSmtpTransport,
+    AsyncTransport,
+    Message,
+    Tokio1Executor,
+};
+
+use crate::domain::{
+    EmailAttachment,
+    SubscriberEmail,
+};
+use crate::email_client::{
+    DkimSigner,
+    Email,
+    EmailSender,
+    ProviderFailure,
+    RetryPolicy,
+    SentEmail,
+    DEFAULT_SENDER_NAME,
+    DKIM_SIGNATURE_HEADER,
+};
+
+/// The header carrying the [`custom_id`](Email::custom_id) of a message.
+const CUSTOM_ID_HEADER: &str = "X-Custom-ID";
+
+/// How the connection to the SMTP server is secured.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text, only meant for local relays.
+    None,
+    /// Upgrade the connection with `STARTTLS`, usually on port 587.
+    StartTls,
+    /// Connect over TLS straight away, usually on port 465.
+    Implicit,
+}
+
+/// The SMTP server relaying the emails of an [`SmtpClient`].
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct SmtpServer {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    /// Authenticate with `AUTH PLAIN` or `AUTH LOGIN` when both `username`
+    /// and `password` are set.
+    pub username: Option<String>,
+    #[derivative(Debug = "ignore")]
+    pub password: Option<String>,
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct SmtpClient {
+    #[derivative(Debug = "ignore")]
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+    reply_to: Option<Mailbox>,
+    retry_policy: RetryPolicy,
+    max_connections: usize,
+    dkim_signer: Option<DkimSigner>,
+}
+
+impl SmtpClient {
+    /// Relay emails through `server`, over a pool of up to `max_connections`
+    /// connections.
+    pub fn new(
+        server: SmtpServer,
+        sender: SubscriberEmail,
+        timeout_secs: u64,
+        max_connections: usize,
+    ) -> Result<Self, anyhow::Error> {
+        let tls = match server.tls {
+            SmtpTls::None => Tls::None,
+            SmtpTls::StartTls => Tls::Required(TlsParameters::new(server.host.clone())?),
+            SmtpTls::Implicit => Tls::Wrapper(TlsParameters::new(server.host.clone())?),
+        };
+        let max_connections = max_connections.max(1);
+        let mut transport = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&server.host)
+            .port(server.port)
+            .tls(tls)
+            .timeout(Some(Duration::from_secs(timeout_secs)))
+            .pool_config(PoolConfig::new().max_size(max_connections as u32));
+        if let (Some(username), Some(password)) = (server.username, server.password) {
+            transport = transport
+                .credentials(Credentials::new(username, password))
+                .authentication(vec![Mechanism::Plain, Mechanism::Login]);
+        }
+        let sender = Mailbox::new(
+            Some(DEFAULT_SENDER_NAME.to_string()),
+            sender
+                .as_ref()
+                .parse()
+                .context(format!("Invalid sender: {}", sender.as_ref()))?,
+        );
+
+        Ok(Self {
+            transport: transport.build(),
+            sender,
+            reply_to: None,
+            retry_policy: RetryPolicy::none(),
+            max_connections,
+            dkim_signer: None,
+        })
+    }
+
+    /// Send the emails under `sender_name` instead of the
+    /// [default](DEFAULT_SENDER_NAME) one.
+    pub fn with_sender_name(mut self, sender_name: String) -> Self {
+        self.sender = Mailbox::new(Some(sender_name), self.sender.email);
+        self
+    }
+
+    /// Direct the replies of the recipients to `reply_to` instead of the
+    /// sender.
+    pub fn with_reply_to(mut self, reply_to: SubscriberEmail) -> Result<Self, anyhow::Error> {
+        self.reply_to = Some(Mailbox::new(
+            None,
+            reply_to
+                .as_ref()
+                .parse()
+                .context(format!("Invalid reply-to: {}", reply_to.as_ref()))?,
+        ));
+        Ok(self)
+    }
+
+    /// Retry transient failures, the `4xx` replies and timeouts, according to
+    /// `retry_policy`.
+    ///
+    /// By default every email is attempted only once.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sign every message with DKIM.
+    pub fn with_dkim_signer(mut self, dkim_signer: DkimSigner) -> Self {
+        self.dkim_signer = Some(dkim_signer);
+        self
+    }
+
+    /// The `Message-ID` header of the message is returned as its
+    /// [`message_id`](SentEmail::message_id).
+    async fn send_with_retry(&self, email: &Email<'_>) -> Result<SentEmail, anyhow::Error> {
+        let message = self.message(email)?;
+        let sent_email = SentEmail {
+            message_id: message.headers().get_raw("Message-ID").map(str::to_string),
+            ..SentEmail::default()
+        };
+        let mut attempt = 1;
+        loop {
+            let error = match self.transport.send(message.clone()).await {
+                Ok(_) => return Ok(sent_email),
+                Err(e) => e,
+            };
+            let delay = self.retry_policy.delay_after(attempt);
+            if !(error.is_transient() || error.is_timeout())
+                || attempt >= self.retry_policy.max_attempts
+            {
+                // a permanent reply rejects the message, the server is reachable,
+                // and a transient reply defers it without sending it
+                let error = if error.is_permanent() || error.is_client() {
+                    anyhow::Error::new(error)
+                } else if error.is_transient() {
+                    ProviderFailure::unsent(error).into()
+                } else {
+                    ProviderFailure::new(error).into()
+                };
+                return Err(error.context(format!("Giving up after {} attempt(s)", attempt)));
+            }
+            tracing::warn!(
+                "Attempt {} to send email failed, retrying in {:?}: {}",
+                attempt,
+                delay,
+                error
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// A `multipart/alternative` message carrying both parts of `email`.
+    ///
+    /// The inline images are wrapped with the alternative parts in a
+    /// `multipart/related` part, and the other attachments are added next to
+    /// it in a `multipart/mixed` message.
+    ///
+    /// The message is signed once complete, when a DKIM signer is set.
+    fn message(&self, email: &Email<'_>) -> Result<Message, anyhow::Error> {
+        let (inline_attachments, attachments): (Vec<_>, Vec<_>) = email
+            .attachments
+            .iter()
+            .partition(|attachment| attachment.is_inline());
+        let mut body = MultiPart::alternative_plain_html(
+            email.text_part.to_string(),
+            email.html_part.to_string(),
+        );
+        if !inline_attachments.is_empty() {
+            body = inline_attachments
+                .into_iter()
+                .try_fold(MultiPart::related().multipart(body), |body, attachment| {
+                    attachment_part(attachment).map(|part| body.singlepart(part))
+                })?;
+        }
+        if !attachments.is_empty() {
+            body = attachments
+                .into_iter()
+                .try_fold(MultiPart::mixed().multipart(body), |body, attachment| {
+                    attachment_part(attachment).map(|part| body.singlepart(part))
+                })?;
+        }
+        let mut message = Message::builder()
+            .from(self.sender.clone())
+            .to(Mailbox::new(
+                email.recipient_name.map(str::to_string),
+                email.recipient.as_ref().parse()?,
+            ))
+            .subject(email.subject)
+            .message_id(None);
+        if let Some(reply_to) = &self.reply_to {
+            message = message.reply_to(reply_to.clone());
+        }
+        let mut message = message
+            .multipart(body)
+            .context("Failed to build the MIME message")?;
+        if let Some(custom_id) = &email.custom_id {
+            message.headers_mut().insert_raw(HeaderValue::new(
+                HeaderName::new_from_ascii_str(CUSTOM_ID_HEADER),
+                custom_id.clone(),
+            ));
+        }
+        for (name, value) in &email.headers {
+            message.headers_mut().insert_raw(HeaderValue::new(
+                HeaderName::new_from_ascii_str(name),
+                value.clone(),
+            ));
+        }
+        if let Some(dkim_signer) = &self.dkim_signer {
+            let signature = dkim_signer.signature(&message.formatted())?;
+            message.headers_mut().insert_raw(HeaderValue::new(
+                HeaderName::new_from_ascii_str(DKIM_SIGNATURE_HEADER),
+                signature,
+            ));
+        }
+        Ok(message)
+    }
+}
+
+fn attachment_part(attachment: &EmailAttachment) -> Result<SinglePart, anyhow::Error> {
+    let content_type = ContentType::parse(attachment.content_type()).context(format!(
+        "Invalid content type: {}",
+        attachment.content_type()
+    ))?;
+    let part = match attachment.content_id() {
+        Some(content_id) => Attachment::new_inline(content_id.to_string()),
+        None => Attachment::new(attachment.filename().to_string()),
+    };
+    Ok(part.body(attachment.content().to_vec(), content_type))
+}
+
+#[async_trait]
+impl EmailSender for SmtpClient {
+    /// Send each email in its own SMTP transaction, with up to
+    /// `max_connections` transactions in flight.
+    async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+        // the futures are built upfront: the compiler cannot prove that a lazy
+        // `map` over the emails is `Send`
+        let messages = emails
+            .iter()
+            .map(|email| async move {
+                self.send_with_retry(email).await.with_context(|| {
+                    format!(
+                        "Error sending email to: {} with subject: {}",
+                        email.recipient.as_ref(),
+                        email.subject
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        stream::iter(messages)
+            .buffered(self.max_connections)
+            .collect()
+            .await
+    }
+
+    fn max_concurrent_requests(&self) -> usize {
+        self.max_connections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use claim::{
+        assert_err,
+        assert_ok,
+    };
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{
+        Paragraph,
+        Sentence,
+    };
+    use fake::Fake;
+    use tokio::io::{
+        AsyncBufReadExt,
+        AsyncWriteExt,
+        BufReader,
+    };
+    use tokio::net::TcpListener;
+
+    use crate::email_client::dkim::tests::{
+        dkim_signer,
+        split_signature,
+    };
+
+    use super::*;
+
+    /// What a minimal SMTP server received.
+    #[derive(Default)]
+    struct Received {
+        connections: usize,
+        auth: Vec<String>,
+        messages: Vec<String>,
+    }
+
+    /// Start a minimal SMTP server on localhost.
+    ///
+    /// It answers the `DATA` of each message with the next of `data_replies`,
+    /// repeating the last one once they run out.
+    async fn smtp_stand_in(data_replies: Vec<&'static str>) -> (u16, Arc<Mutex<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Received::default()));
+        let data_replies = Arc::new(Mutex::new(data_replies));
+        let server_received = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                server_received.lock().unwrap().connections += 1;
+                let received = server_received.clone();
+                let data_replies = data_replies.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = socket.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let command = line.to_uppercase();
+                        let reply = if command.starts_with("EHLO") {
+                            "250-localhost\r\n250 AUTH PLAIN LOGIN\r\n".to_string()
+                        } else if command.starts_with("AUTH") {
+                            received.lock().unwrap().auth.push(line);
+                            "235 2.7.0 Authentication successful\r\n".to_string()
+                        } else if command == "DATA" {
+                            writer
+                                .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                                .await
+                                .unwrap();
+                            let mut message = String::new();
+                            while let Ok(Some(line)) = lines.next_line().await {
+                                if line == "." {
+                                    break;
+                                }
+                                message.push_str(&line);
+                                message.push('\n');
+                            }
+                            received.lock().unwrap().messages.push(message);
+                            let mut data_replies = data_replies.lock().unwrap();
+                            if data_replies.len() > 1 {
+                                data_replies.remove(0).to_string()
+                            } else {
+                                data_replies[0].to_string()
+                            }
+                        } else if command == "QUIT" {
+                            writer.write_all(b"221 Bye\r\n").await.unwrap();
+                            break;
+                        } else {
+                            "250 OK\r\n".to_string()
+                        };
+                        writer.write_all(reply.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        (port, received)
+    }
+
+    fn server(port: u16) -> SmtpServer {
+        SmtpServer {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+        }
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::try_from(SafeEmail().fake::<String>()).unwrap()
+    }
+
+    fn sentence() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn paragraph() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_a_multipart_alternative_message() {
+        let (port, received) = smtp_stand_in(vec!["250 OK\r\n"]).await;
+        let smtp_client = SmtpClient::new(server(port), email(), 10, 1).unwrap();
+        let recipient = email();
+
+        let outcome = smtp_client
+            .send_email(Email::new(
+                recipient.clone(),
+                "any_subject",
+                "<p>any_html</p>",
+                "any_text",
+            ))
+            .await;
+
+        let message_id = assert_ok!(outcome).message_id.unwrap();
+        let received = received.lock().unwrap();
+        assert_eq!(received.messages.len(), 1);
+        let message = &received.messages[0];
+        assert!(message.contains(&format!("Message-ID: {}", message_id)));
+        assert!(message.contains(&format!("To: {}", recipient.as_ref())));
+        assert!(message.contains("Subject: any_subject"));
+        assert!(message.contains("Content-Type: multipart/alternative"));
+        assert!(message.contains("Content-Type: text/plain"));
+        assert!(message.contains("any_text"));
+        assert!(message.contains("Content-Type: text/html"));
+        assert!(message.contains("<p>any_html</p>"));
+        assert!(received.auth.is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_emails_sends_attachments_and_inline_images() {
+        let (port, received) = smtp_stand_in(vec!["250 OK\r\n"]).await;
+        let smtp_client = SmtpClient::new(server(port), email(), 10, 1).unwrap();
+        let attachments = [
+            EmailAttachment::parse(
+                "report.pdf".to_string(),
+                "application/pdf".to_string(),
+                b"%PDF-\xff\xfe".to_vec(),
+                None,
+            )
+            .unwrap(),
+            EmailAttachment::parse(
+                "logo.png".to_string(),
+                "image/png".to_string(),
+                b"\x89PNG\r\n".to_vec(),
+                Some("logo".to_string()),
+            )
+            .unwrap(),
+        ];
+        let email = Email {
+            attachments: &attachments,
+            ..Email::new(email(), "any_subject", "<img src=\"cid:logo\">", "any_text")
+        };
+
+        let results = smtp_client.send_emails(std::slice::from_ref(&email)).await;
+
+        assert!(results.iter().all(Result::is_ok));
+        let received = received.lock().unwrap();
+        let message = &received.messages[0];
+        assert!(message.contains("Content-Type: multipart/mixed"));
+        assert!(message.contains("Content-Type: multipart/related"));
+        assert!(message.contains("Content-Type: multipart/alternative"));
+        assert!(message.contains("Content-Disposition: attachment; filename=\"report.pdf\""));
+        assert!(message.contains("Content-Type: application/pdf"));
+        assert!(message.contains(&base64::encode(b"%PDF-\xff\xfe")));
+        assert!(message.contains("Content-ID: <logo>"));
+        assert!(message.contains("Content-Disposition: inline"));
+        assert!(message.contains(&base64::encode(b"\x89PNG\r\n")));
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_sender_identity_and_the_recipient_name() {
+        let (port, received) = smtp_stand_in(vec!["250 OK\r\n"]).await;
+        let sender = email();
+        let reply_to = email();
+        let recipient = email();
+        let smtp_client = SmtpClient::new(server(port), sender.clone(), 10, 1)
+            .unwrap()
+            .with_sender_name("Weekly Digest".to_string())
+            .with_reply_to(reply_to.clone())
+            .unwrap();
+
+        let outcome = smtp_client
+            .send_email(Email {
+                recipient_name: Some("Ursula Le Guin"),
+                custom_id: Some("issue:subscriber".to_string()),
+                ..Email::new(recipient.clone(), "any_subject", "any_html", "any_text")
+            })
+            .await;
+
+        assert_ok!(outcome);
+        let received = received.lock().unwrap();
+        let message = &received.messages[0];
+        assert!(message.contains(&format!("From: \"Weekly Digest\" <{}>", sender.as_ref())));
+        assert!(message.contains(&format!("Reply-To: {}", reply_to.as_ref())));
+        assert!(message.contains(&format!("To: \"Ursula Le Guin\" <{}>", recipient.as_ref())));
+        assert!(message.contains("X-Custom-ID: issue:subscriber"));
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_extra_headers() {
+        let (port, received) = smtp_stand_in(vec!["250 OK\r\n"]).await;
+        let smtp_client = SmtpClient::new(server(port), email(), 10, 1).unwrap();
+
+        let outcome = smtp_client
+            .send_email(Email {
+                headers: vec![
+                    ("List-Unsubscribe", "<https://any_url>".to_string()),
+                    (
+                        "List-Unsubscribe-Post",
+                        "List-Unsubscribe=One-Click".to_string(),
+                    ),
+                ],
+                ..Email::new(email(), "any_subject", "any_html", "any_text")
+            })
+            .await;
+
+        assert_ok!(outcome);
+        let received = received.lock().unwrap();
+        let message = &received.messages[0];
+        assert!(message.contains("List-Unsubscribe: <https://any_url>"));
+        assert!(message.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+    }
+
+    #[tokio::test]
+    async fn send_email_signs_the_message_with_dkim() {
+        let (port, received) = smtp_stand_in(vec!["250 OK\r\n"]).await;
+        let smtp_client = SmtpClient::new(server(port), email(), 10, 1)
+            .unwrap()
+            .with_dkim_signer(dkim_signer());
+
+        let outcome = smtp_client
+            .send_email(Email {
+                headers: vec![("List-Unsubscribe", "<https://any_url>".to_string())],
+                ..Email::new(email(), &sentence(), &paragraph(), &paragraph())
+            })
+            .await;
+
+        assert_ok!(outcome);
+        let received = received.lock().unwrap();
+        let message = received.messages[0].replace('\n', "\r\n");
+        let (signature, unsigned) = split_signature(&message);
+        let timestamp = signature
+            .split("; ")
+            .find_map(|tag| tag.strip_prefix("t="))
+            .unwrap()
+            .parse()
+            .unwrap();
+        // the signature of the message as sent, the signer is tested against
+        // independently computed signatures
+        let expected = dkim_signer()
+            .signature_at(unsigned.as_bytes(), timestamp)
+            .unwrap();
+        let without_whitespace = |value: &str| value.split_whitespace().collect::<String>();
+        assert_eq!(
+            without_whitespace(&signature),
+            without_whitespace(&expected)
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_authenticates_with_the_credentials() {
+        let (port, received) = smtp_stand_in(vec!["250 OK\r\n"]).await;
+        let smtp_server = SmtpServer {
+            username: Some("any_user".to_string()),
+            password: Some("any_password".to_string()),
+            ..server(port)
+        };
+        let smtp_client = SmtpClient::new(smtp_server, email(), 10, 1).unwrap();
+
+        let outcome = smtp_client
+            .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+            .await;
+
+        assert_ok!(outcome);
+        // the pool may open an idle connection besides the one used to send
+        let received = received.lock().unwrap();
+        let auth = format!("AUTH PLAIN {}", base64::encode("\0any_user\0any_password"));
+        assert!(!received.auth.is_empty());
+        assert!(received.auth.iter().all(|line| *line == auth));
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_transient_failures() {
+        let (port, received) = smtp_stand_in(vec!["451 Try again later\r\n", "250 OK\r\n"]).await;
+        let smtp_client = SmtpClient::new(server(port), email(), 10, 1)
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(10),
+                jitter: 0.0,
+            });
+
+        let outcome = smtp_client
+            .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+            .await;
+
+        assert_ok!(outcome);
+        assert_eq!(received.lock().unwrap().messages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_permanent_failures() {
+        let (port, received) = smtp_stand_in(vec!["550 Mailbox unavailable\r\n"]).await;
+        let smtp_client = SmtpClient::new(server(port), email(), 10, 1)
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(10),
+                jitter: 0.0,
+            });
+
+        let outcome = smtp_client
+            .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+            .await;
+
+        assert_err!(outcome);
+        assert_eq!(received.lock().unwrap().messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_provider_failures_of_unsent_emails() {
+        let (port, _) = smtp_stand_in(vec!["451 Try again later\r\n"]).await;
+        let smtp_client = SmtpClient::new(server(port), email(), 10, 1).unwrap();
+
+        let error = smtp_client
+            .send_email(Email::new(email(), &sentence(), &paragraph(), &paragraph()))
+            .await
+            .unwrap_err();
+
+        assert!(!ProviderFailure::find(&error).unwrap().may_be_sent());
+    }
+
+    #[tokio::test]
+    async fn send_emails_reuses_pooled_connections() {
+        let (port, received) = smtp_stand_in(vec!["250 OK\r\n"]).await;
+        let smtp_client = SmtpClient::new(server(port), email(), 10, 1).unwrap();
+        let subject = sentence();
+        let content = paragraph();
+        let emails = (0..6)
+            .map(|_| Email::new(email(), &subject, &content, &content))
+            .collect::<Vec<_>>();
+
+        let results = smtp_client.send_emails(&emails).await;
+
+        assert_eq!(results.len(), 6);
+        assert!(results.iter().all(Result::is_ok));
+        let received = received.lock().unwrap();
+        assert_eq!(received.messages.len(), 6);
+        assert!(received.connections < emails.len());
+    }
+}
@@ -0,0 +1,181 @@
+use actix_web::body::{
+    Body,
+    ResponseBody,
+};
+use actix_web::http::{
+    HeaderName,
+    HeaderValue,
+    StatusCode,
+};
+use actix_web::HttpResponse;
+use anyhow::Context;
+use chrono::Utc;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sqlx::types::Json;
+use sqlx::{
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+use crate::domain::IdempotencyKey;
+
+#[derive(Deserialize, Serialize)]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+struct SavedResponse {
+    response_status_code: Option<i16>,
+    response_headers: Option<Json<Vec<HeaderPairRecord>>>,
+    response_body: Option<Vec<u8>>,
+}
+
+pub enum NextAction {
+    /// The key has not been seen yet: the request must be processed inside the
+    /// returned transaction and its response saved with [`save_response`].
+    StartProcessing(Box<Transaction<'static, Postgres>>),
+    /// The key has already been used: the stored response must be replayed.
+    ReturnSavedResponse(HttpResponse),
+}
+
+/// Reserve `idempotency_key` for `user_id` or retrieve the response stored for
+/// it.
+///
+/// The reservation row stays locked until the returned transaction is
+/// committed, so a concurrent request with the same key waits for the first one
+/// to complete and then replays its response.
+#[tracing::instrument(name = "Reserving idempotency key", skip(postgres_pool))]
+pub async fn try_processing(
+    postgres_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: &Uuid,
+) -> Result<NextAction, anyhow::Error> {
+    let mut transaction = postgres_pool
+        .begin()
+        .await
+        .context("Failed to start SQL transaction to reserve an idempotency key")?;
+    let inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        Utc::now()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to reserve idempotency key")?
+    .rows_affected();
+    if inserted_rows > 0 {
+        Ok(NextAction::StartProcessing(Box::new(transaction)))
+    } else {
+        let saved_response = get_saved_response(postgres_pool, idempotency_key, user_id)
+            .await?
+            .context("Expected a saved response, but none was found")?;
+        Ok(NextAction::ReturnSavedResponse(saved_response))
+    }
+}
+
+async fn get_saved_response(
+    postgres_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: &Uuid,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    let saved_response = sqlx::query_as!(
+        SavedResponse,
+        r#"
+        SELECT
+            response_status_code,
+            response_headers as "response_headers: Json<Vec<HeaderPairRecord>>",
+            response_body
+        FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(postgres_pool)
+    .await
+    .context("Failed to retrieve saved response")?;
+
+    match saved_response {
+        Some(SavedResponse {
+            response_status_code: Some(status_code),
+            response_headers: Some(Json(headers)),
+            response_body: Some(body),
+        }) => {
+            let status_code = StatusCode::from_u16(status_code as u16)
+                .context("Invalid status code in saved response")?;
+            let mut response = HttpResponse::build(status_code);
+            for HeaderPairRecord { name, value } in headers {
+                response.append_header((
+                    HeaderName::from_bytes(name.as_bytes())
+                        .context("Invalid header name in saved response")?,
+                    HeaderValue::from_bytes(&value)
+                        .context("Invalid header value in saved response")?,
+                ));
+            }
+            Ok(Some(response.body(body)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Store `http_response` as the response for `idempotency_key` and give it
+/// back.
+///
+/// Only fully buffered bodies can be stored: streaming responses are rejected.
+#[tracing::instrument(
+    name = "Saving response for idempotency key",
+    skip(postgres_transaction, http_response)
+)]
+pub async fn save_response(
+    postgres_transaction: &mut Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: &Uuid,
+    mut http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    let body = match http_response.take_body() {
+        ResponseBody::Body(body) | ResponseBody::Other(body) => body,
+    };
+    let body = match body {
+        Body::None | Body::Empty => Vec::new(),
+        Body::Bytes(bytes) => bytes.to_vec(),
+        Body::Message(_) => anyhow::bail!("Streaming responses cannot be saved"),
+    };
+    let status_code = http_response.status().as_u16() as i16;
+    let headers = http_response
+        .headers()
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect::<Vec<_>>();
+
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET response_status_code = $3, response_headers = $4, response_body = $5
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        Json(headers) as _,
+        body.as_slice()
+    )
+    .execute(postgres_transaction)
+    .await
+    .context("Failed to save response")?;
+
+    Ok(http_response.set_body(Body::from(body)))
+}
@@ -1,5 +1,8 @@
 //! The `newsletter` library.
 pub mod app;
+pub mod delivery;
 pub mod domain;
 pub mod email_client;
+pub mod idempotency;
 pub mod routes;
+pub mod templates;
@@ -1,11 +1,43 @@
+pub use email_events::email_events;
 pub use errors::NewsletterError;
 pub use health_check::health_check;
-pub use newsletters::newsletters;
+pub use metrics::metrics;
+pub use newsletter_drafts::{
+    create_draft,
+    publish_draft,
+    test_draft,
+    update_draft,
+};
+pub use newsletter_issues::{
+    newsletter_issue,
+    newsletter_issues,
+};
+pub use newsletters::{
+    newsletter_report,
+    newsletters,
+    MAX_ARTICLE_SIZE,
+};
+pub use scheduled_newsletters::{
+    cancel_newsletter,
+    reschedule_newsletter,
+    scheduled_newsletters,
+};
 pub use subscriptions::subscribe;
 pub use subscriptions_confirm::confirm;
+pub use subscriptions_unsubscribe::{
+    unsubscribe,
+    unsubscribe_form,
+};
 
+mod authentication;
+mod email_events;
 mod errors;
 mod health_check;
+mod metrics;
+mod newsletter_drafts;
+mod newsletter_issues;
 mod newsletters;
+mod scheduled_newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
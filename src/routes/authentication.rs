@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use actix_web::http::HeaderMap;
+use actix_web::web;
+use anyhow::Context;
+use argon2::{
+    Argon2,
+    PasswordHash,
+    PasswordVerifier,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::WebhookCredentials;
+use crate::routes::NewsletterError;
+
+/// Authenticate the publisher with Basic credentials and return its user id.
+///
+/// The `username` and `uuid` fields of the current span are filled in on the
+/// way.
+pub async fn authenticate(
+    request: &web::HttpRequest,
+    postgres_connection: &PgPool,
+) -> Result<Uuid, NewsletterError> {
+    let credentials = get_credentials(request.headers()).map_err(NewsletterError::AuthError)?;
+    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    let authenticated_uuid = validate_credentials(credentials, postgres_connection)
+        .await
+        .map_err(NewsletterError::AuthError)?;
+    tracing::Span::current().record("uuid", &tracing::field::display(authenticated_uuid));
+    Ok(authenticated_uuid)
+}
+
+/// Authenticate the email provider calling a webhook with the Basic
+/// credentials it was configured with.
+pub fn authenticate_webhook(
+    request: &web::HttpRequest,
+    webhook_credentials: &WebhookCredentials,
+) -> Result<(), NewsletterError> {
+    let credentials = get_credentials(request.headers()).map_err(NewsletterError::AuthError)?;
+    // both fields are always compared, in constant time, not to leak which
+    // one is wrong or how much of it is right
+    let username_matches = constant_time_eq(&credentials.username, &webhook_credentials.username);
+    let password_matches = constant_time_eq(&credentials.password, &webhook_credentials.password);
+    if !(username_matches & password_matches) {
+        return Err(NewsletterError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials"
+        )));
+    }
+    Ok(())
+}
+
+fn constant_time_eq(left: &str, right: &str) -> bool {
+    left.len() == right.len()
+        && left
+            .bytes()
+            .zip(right.bytes())
+            .fold(0, |difference, (l, r)| difference | (l ^ r))
+            == 0
+}
+
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[tracing::instrument(name = "Retrieving user credentials", skip(headers))]
+fn get_credentials(headers: &HeaderMap) -> anyhow::Result<Credentials> {
+    let authorization_header: &str = headers
+        .get("Authorization")
+        .context("Missing `Authorization` header")?
+        .to_str()
+        .context("Invalid `Authorization` content")?;
+    let encoded_credentials = authorization_header
+        .strip_prefix("Basic ")
+        .context("Authorization scheme is not Basic")?;
+    let decoded_credentials_bytes =
+        base64::decode(encoded_credentials).context("Credentials cannot be base64 decoded")?;
+    let decoded_credentials = String::from_utf8(decoded_credentials_bytes)
+        .context("Invalid credentials: not UTF8 chars")?;
+    let mut credentials = decoded_credentials.split(":");
+    let username = credentials
+        .next()
+        .context("Invalid credentials: missing username")?;
+    let password = credentials
+        .next()
+        .context("Invalid credentials: missing password")?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    })
+}
+
+struct AuthenticatedUser {
+    id: Uuid,
+    phc_password: String,
+}
+
+#[tracing::instrument(
+    name = "Validating user credentials",
+    skip(credentials, postgres_connection)
+)]
+async fn validate_credentials(
+    credentials: Credentials,
+    postgres_connection: &PgPool,
+) -> anyhow::Result<Uuid> {
+    let user = retrieve_authenticated_user(&credentials.username, postgres_connection)
+        .await
+        .unwrap_or_else(|_| AuthenticatedUser {
+            id: Default::default(),
+            phc_password: "$argon2id$v=19$m=15000,t=2,p=1$gZiV/M1gPc22ElAH/Jh1Hw$CWOrkoo7oJBQ/\
+                           iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
+                .to_string(),
+        });
+    let span = tracing::Span::current();
+    let password = credentials.password;
+    let phc_password = user.phc_password;
+    span.in_scope(|| verify_password(password, phc_password))
+        .await?;
+
+    Ok(user.id)
+}
+async fn retrieve_authenticated_user(
+    username: &str,
+    postgres_connection: &PgPool,
+) -> anyhow::Result<AuthenticatedUser> {
+    sqlx::query_as!(
+        AuthenticatedUser,
+        r#"
+        SELECT id,phc_password
+        FROM users
+        WHERE username=$1
+        "#,
+        username,
+    )
+    .fetch_optional(postgres_connection)
+    .await
+    .context("Error fetching user from database")?
+    .context("User not found")
+}
+
+async fn verify_password(candidate_password: String, expected_hash: String) -> anyhow::Result<()> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    rayon::spawn(move || {
+        let pwd_hash =
+            PasswordHash::new(&expected_hash).context("Invalid password format: not PHC format");
+        let password_check_result = pwd_hash.and_then(|hash| {
+            Argon2::default()
+                .verify_password(candidate_password.as_bytes(), &hash)
+                .context("Wrong password")
+        });
+
+        if sender.send(password_check_result).is_err() {
+            tracing::warn!("Error sending password check result to the receiver channel");
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(1), receiver)
+        .await
+        .context("Error getting password check response: expired timeout (1 seconds)")?
+        .context("Error getting password check response")?
+}
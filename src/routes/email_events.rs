@@ -0,0 +1,261 @@
+use std::fmt;
+
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use chrono::Utc;
+use serde::de::value::{
+    MapAccessDeserializer,
+    SeqAccessDeserializer,
+};
+use serde::de::{
+    MapAccess,
+    SeqAccess,
+    Visitor,
+};
+use serde::{
+    Deserialize,
+    Deserializer,
+};
+use sqlx::{
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+use crate::domain::WebhookCredentials;
+use crate::routes::authentication::authenticate_webhook;
+use crate::routes::NewsletterError;
+
+/// The provider posts either a single event or, when grouping is enabled, an
+/// array of events.
+pub enum EmailEvents {
+    One(EmailEvent),
+    Many(Vec<EmailEvent>),
+}
+
+/// A delivery event reported by the email provider.
+#[derive(Deserialize)]
+pub struct EmailEvent {
+    event: String,
+    email: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<u64>,
+    /// Only set on `bounce` events.
+    #[serde(default)]
+    hard_bounce: bool,
+}
+
+/// The recipient of the message an event is about.
+struct EventRecipient {
+    subscriber_id: Option<Uuid>,
+    issue_id: Option<Uuid>,
+}
+
+// `#[serde(untagged)]` cannot be used: it buffers the content, which breaks
+// the numbers of `serde_json` with `arbitrary_precision`
+impl<'de> Deserialize<'de> for EmailEvents {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EmailEventsVisitor;
+
+        impl<'de> Visitor<'de> for EmailEventsVisitor {
+            type Value = EmailEvents;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an email event or an array of email events")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, events: A) -> Result<Self::Value, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(events)).map(EmailEvents::Many)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, event: A) -> Result<Self::Value, A::Error> {
+                EmailEvent::deserialize(MapAccessDeserializer::new(event)).map(EmailEvents::One)
+            }
+        }
+
+        deserializer.deserialize_any(EmailEventsVisitor)
+    }
+}
+
+impl EmailEvents {
+    fn into_vec(self) -> Vec<EmailEvent> {
+        match self {
+            EmailEvents::One(event) => vec![event],
+            EmailEvents::Many(events) => events,
+        }
+    }
+}
+
+impl EmailEvent {
+    /// The status of a subscriber that must not be emailed anymore after
+    /// this event.
+    ///
+    /// Soft bounces are temporary failures and leave the subscriber as is.
+    fn subscription_status(&self) -> Option<&'static str> {
+        match self.event.as_str() {
+            "bounce" if self.hard_bounce => Some("bounced"),
+            "blocked" => Some("blocked"),
+            "spam" => Some("complained"),
+            "unsub" => Some("unsubscribed"),
+            _ => None,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Receiving email provider events",
+    skip(email_events, postgres_connection, webhook_credentials, request)
+)]
+pub async fn email_events(
+    email_events: web::Json<EmailEvents>,
+    postgres_connection: web::Data<PgPool>,
+    webhook_credentials: web::Data<WebhookCredentials>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate_webhook(&request, webhook_credentials.as_ref())?;
+    let mut transaction = postgres_connection
+        .begin()
+        .await
+        .context("Failed to start SQL transaction to store email events")?;
+    for email_event in email_events.into_inner().into_vec() {
+        store_email_event(&email_event, &mut transaction).await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store email events")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(
+name = "Storing email event",
+skip(email_event, postgres_transaction),
+fields(
+event = % email_event.event,
+message_id = ?email_event.message_id,
+)
+)]
+async fn store_email_event(
+    email_event: &EmailEvent,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), NewsletterError> {
+    let provider_message_id = email_event.message_id.map(|id| id.to_string());
+    let recipient = get_event_recipient(
+        provider_message_id.as_deref(),
+        &email_event.email,
+        postgres_transaction,
+    )
+    .await
+    .context("Failed to retrieve the recipient of the email event")?;
+    insert_email_event(
+        email_event,
+        provider_message_id.as_deref(),
+        &recipient,
+        postgres_transaction,
+    )
+    .await
+    .context("Failed to store email event")?;
+
+    match (email_event.subscription_status(), recipient.subscriber_id) {
+        (Some(status), Some(subscriber_id)) => {
+            update_subscription_status(&subscriber_id, status, postgres_transaction)
+                .await
+                .context("Failed to update subscription status")?;
+        }
+        (Some(_), None) => {
+            tracing::warn!("Email event about an unknown recipient");
+        }
+        (None, _) => {}
+    }
+    Ok(())
+}
+
+/// Match the event to a delivery task by its provider message id, or to a
+/// subscriber by email for the messages sent outside of an issue, such as
+/// confirmation emails.
+async fn get_event_recipient(
+    provider_message_id: Option<&str>,
+    email: &str,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<EventRecipient, sqlx::Error> {
+    if let Some(provider_message_id) = provider_message_id {
+        let delivery_task = sqlx::query!(
+            r#"
+            SELECT subscriber_id, issue_id
+            FROM issue_delivery_tasks
+            WHERE provider_message_id = $1
+            "#,
+            provider_message_id
+        )
+        .fetch_optional(&mut *postgres_transaction)
+        .await?;
+        if let Some(delivery_task) = delivery_task {
+            return Ok(EventRecipient {
+                subscriber_id: Some(delivery_task.subscriber_id),
+                issue_id: Some(delivery_task.issue_id),
+            });
+        }
+    }
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(&mut *postgres_transaction)
+    .await?;
+    Ok(EventRecipient {
+        subscriber_id: subscriber.map(|subscriber| subscriber.id),
+        issue_id: None,
+    })
+}
+
+async fn insert_email_event(
+    email_event: &EmailEvent,
+    provider_message_id: Option<&str>,
+    recipient: &EventRecipient,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO delivery_events
+            (id, event, email, provider_message_id, subscriber_id, issue_id, received_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        email_event.event,
+        email_event.email,
+        provider_message_id,
+        recipient.subscriber_id,
+        recipient.issue_id,
+        Utc::now()
+    )
+    .execute(postgres_transaction)
+    .await?;
+    Ok(())
+}
+
+async fn update_subscription_status(
+    subscriber_id: &Uuid,
+    status: &str,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        status
+    )
+    .execute(postgres_transaction)
+    .await?;
+    Ok(())
+}
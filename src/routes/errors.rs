@@ -10,7 +10,7 @@ use actix_web::{
 };
 
 #[derive(thiserror::Error)]
-///! Error handling a newsletter route
+/// Error handling a newsletter route
 pub enum NewsletterError {
     // this is the Display trait implementation
     #[error("Invalid data: {0}")]
@@ -20,6 +20,8 @@ pub enum NewsletterError {
     // soruce as String but maps to a different error code
     #[error("Confirmation failed for missing token: {0}")]
     MissingTokenError(String),
+    #[error("Resource not found: {0}")]
+    NotFoundError(String),
     #[error("Authentication Error: {0}")]
     AuthError(#[source] anyhow::Error),
     #[error("Unexpected internal error: {0}")]
@@ -44,6 +46,7 @@ impl ResponseError for NewsletterError {
             NewsletterError::ValidationError(_) => StatusCode::BAD_REQUEST,
             NewsletterError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             NewsletterError::MissingTokenError(_) => StatusCode::NOT_FOUND,
+            NewsletterError::NotFoundError(_) => StatusCode::NOT_FOUND,
             NewsletterError::AuthError(_) => StatusCode::UNAUTHORIZED,
         }
     }
@@ -53,8 +56,9 @@ impl ResponseError for NewsletterError {
             NewsletterError::ValidationError(e) => HttpResponse::BadRequest().json(e),
             NewsletterError::UnexpectedError(_) => HttpResponse::InternalServerError().finish(),
             NewsletterError::MissingTokenError(missing_token) => {
-                HttpResponse::NotFound().json(&format!("Token: {} not found", missing_token))
+                HttpResponse::NotFound().json(format!("Token: {} not found", missing_token))
             }
+            NewsletterError::NotFoundError(e) => HttpResponse::NotFound().json(e),
             NewsletterError::AuthError(_) => HttpResponse::Unauthorized()
                 .append_header((header::WWW_AUTHENTICATE, "Basic realm=\"publish\""))
                 .finish(),
@@ -1,8 +1,35 @@
 use actix_web::{
-    HttpRequest,
+    web,
     HttpResponse,
 };
+use serde::Serialize;
 
-pub async fn health_check(_req: HttpRequest) -> HttpResponse {
-    HttpResponse::Ok().finish()
+use crate::email_client::{
+    CircuitState,
+    EmailSender,
+    RemainingQuota,
+};
+
+#[derive(Serialize)]
+struct HealthReport {
+    email_sender: EmailSenderReport,
+}
+
+/// The email provider may be unavailable while the app itself is healthy, so
+/// its state is reported without failing the check.
+#[derive(Serialize)]
+struct EmailSenderReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    circuit_state: Option<CircuitState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining_quota: Option<RemainingQuota>,
+}
+
+pub async fn health_check(email_client: web::Data<dyn EmailSender>) -> HttpResponse {
+    HttpResponse::Ok().json(&HealthReport {
+        email_sender: EmailSenderReport {
+            circuit_state: email_client.circuit_state(),
+            remaining_quota: email_client.remaining_quota(),
+        },
+    })
 }
@@ -0,0 +1,22 @@
+use actix_web::{
+    web,
+    HttpResponse,
+};
+
+use crate::email_client::{
+    render_prometheus,
+    render_remaining_quota,
+    EmailSender,
+};
+
+/// The metrics of the email providers, and the remaining quota of the
+/// current one, in the Prometheus text format.
+pub async fn metrics(email_client: web::Data<dyn EmailSender>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(format!(
+            "{}{}",
+            render_prometheus(&email_client.send_metrics()),
+            render_remaining_quota(email_client.remaining_quota())
+        ))
+}
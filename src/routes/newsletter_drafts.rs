@@ -0,0 +1,370 @@
+use std::convert::TryFrom;
+
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use chrono::Utc;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sqlx::{
+    PgConnection,
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+use crate::delivery::{
+    enqueue_delivery_tasks,
+    get_delivery_report,
+    get_issue_attachments,
+    store_issue_attachments,
+    DeliveryFailure,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    Email,
+    EmailSender,
+};
+use crate::routes::authentication::authenticate;
+use crate::routes::newsletters::{
+    parse_attachments,
+    ArticleAttachment,
+    ArticleContent,
+};
+use crate::routes::NewsletterError;
+use crate::templates::{
+    EmailTemplate,
+    RenderedEmail,
+};
+
+/// The `unsubscribe_link` of the drafts sent to reviewers.
+const TEST_UNSUBSCRIBE_LINK: &str = "#";
+
+#[derive(Deserialize)]
+pub struct Draft {
+    title: String,
+    content: ArticleContent,
+    #[serde(default)]
+    attachments: Vec<ArticleAttachment>,
+}
+
+#[derive(Deserialize)]
+pub struct Reviewers {
+    reviewers: Vec<String>,
+}
+
+/// An issue that is not sent to subscribers until it is published.
+#[derive(Serialize)]
+struct StoredDraft {
+    issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Outcome of sending a draft to its reviewers.
+#[derive(Serialize)]
+struct TestSendReport {
+    issue_id: Uuid,
+    sent: usize,
+    failures: Vec<DeliveryFailure>,
+}
+
+#[tracing::instrument(
+name = "Creating newsletter draft",
+skip(draft, postgres_connection, request),
+fields(
+title = % draft.title,
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn create_draft(
+    draft: web::Json<Draft>,
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    let authenticated_uuid = authenticate(&request, postgres_connection.as_ref()).await?;
+    let content = draft
+        .content
+        .render()
+        .map_err(NewsletterError::ValidationError)?;
+    let attachments =
+        parse_attachments(&draft.attachments).map_err(NewsletterError::ValidationError)?;
+    let mut transaction = postgres_connection
+        .begin()
+        .await
+        .context("Failed to start SQL transaction to store a newsletter draft")?;
+    let stored_draft = insert_draft(
+        &draft.title,
+        &content,
+        &authenticated_uuid,
+        &mut transaction,
+    )
+    .await
+    .context("Failed to store newsletter draft")?;
+    store_issue_attachments(&stored_draft.issue_id, &attachments, &mut transaction)
+        .await
+        .context("Failed to store newsletter draft attachments")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a newsletter draft")?;
+    Ok(HttpResponse::Created().json(&stored_draft))
+}
+
+#[tracing::instrument(
+name = "Updating newsletter draft",
+skip(draft, postgres_connection, request),
+fields(
+title = % draft.title,
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn update_draft(
+    issue_id: web::Path<Uuid>,
+    draft: web::Json<Draft>,
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    let content = draft
+        .content
+        .render()
+        .map_err(NewsletterError::ValidationError)?;
+    let attachments =
+        parse_attachments(&draft.attachments).map_err(NewsletterError::ValidationError)?;
+    let issue_id = issue_id.into_inner();
+    let mut transaction = postgres_connection
+        .begin()
+        .await
+        .context("Failed to start SQL transaction to update a newsletter draft")?;
+    let stored_draft = store_draft_update(&issue_id, &draft.title, &content, &mut transaction)
+        .await
+        .context("Failed to update newsletter draft")?
+        .ok_or_else(|| draft_not_found(&issue_id))?;
+    store_issue_attachments(&issue_id, &attachments, &mut transaction)
+        .await
+        .context("Failed to update newsletter draft attachments")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a newsletter draft")?;
+    Ok(HttpResponse::Ok().json(&stored_draft))
+}
+
+/// Send a draft to reviewers only, leaving it unpublished.
+///
+/// Reviewers are not subscribers: the draft is rendered with their email as
+/// `name` and an inert `unsubscribe_link`.
+#[tracing::instrument(
+name = "Sending newsletter draft to reviewers",
+skip(reviewers, postgres_connection, email_client, request),
+fields(
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn test_draft(
+    issue_id: web::Path<Uuid>,
+    reviewers: web::Json<Reviewers>,
+    postgres_connection: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    let reviewers = reviewers
+        .into_inner()
+        .reviewers
+        .into_iter()
+        .map(SubscriberEmail::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(NewsletterError::ValidationError)?;
+    if reviewers.is_empty() {
+        return Err(NewsletterError::ValidationError(
+            "At least one reviewer is required".to_string(),
+        ));
+    }
+    let issue_id = issue_id.into_inner();
+    let mut connection = postgres_connection
+        .acquire()
+        .await
+        .context("Failed to acquire database connection to retrieve a newsletter draft")?;
+    let draft = get_draft(&issue_id, &mut connection)
+        .await
+        .context("Failed to retrieve newsletter draft")?
+        .ok_or_else(|| draft_not_found(&issue_id))?;
+    let attachments = get_issue_attachments(&issue_id, &mut connection)
+        .await
+        .context("Failed to retrieve newsletter draft attachments")?;
+    let template = EmailTemplate::issue(&draft.html_content, &draft.text_content);
+
+    let contents = reviewers
+        .iter()
+        .map(|recipient| {
+            template.render(&[
+                ("name", recipient.as_ref()),
+                ("unsubscribe_link", TEST_UNSUBSCRIBE_LINK),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let emails = reviewers
+        .into_iter()
+        .zip(&contents)
+        .map(|(recipient, content)| Email {
+            attachments: attachments.as_ref(),
+            ..Email::new(recipient, &draft.title, &content.html, &content.text)
+        })
+        .collect::<Vec<_>>();
+    let outcomes = email_client.send_emails(&emails).await;
+    let failures = emails
+        .iter()
+        .zip(outcomes)
+        .filter_map(|(email, outcome)| {
+            outcome.err().map(|e| DeliveryFailure {
+                subscriber_email: email.recipient.as_ref().to_string(),
+                reason: format!("{:#}", e),
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(&TestSendReport {
+        issue_id,
+        sent: emails.len() - failures.len(),
+        failures,
+    }))
+}
+
+/// Publish a draft through the same delivery queue as
+/// [`newsletters`](crate::routes::newsletters).
+#[tracing::instrument(
+name = "Publishing newsletter draft",
+skip(postgres_connection, request),
+fields(
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn publish_draft(
+    issue_id: web::Path<Uuid>,
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    let issue_id = issue_id.into_inner();
+    let mut transaction = postgres_connection
+        .begin()
+        .await
+        .context("Failed to start SQL transaction to publish a newsletter draft")?;
+    if !mark_draft_as_published(&issue_id, &mut transaction)
+        .await
+        .context("Failed to mark newsletter draft as published")?
+    {
+        return Err(draft_not_found(&issue_id));
+    }
+    enqueue_delivery_tasks(&issue_id, &mut transaction)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    let delivery_report = get_delivery_report(&issue_id, &mut transaction)
+        .await
+        .context("Failed to build delivery report")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter draft")?;
+    Ok(HttpResponse::Ok().json(&delivery_report))
+}
+
+fn draft_not_found(issue_id: &Uuid) -> NewsletterError {
+    NewsletterError::NotFoundError(format!("Draft: {} not found", issue_id))
+}
+
+async fn insert_draft(
+    title: &str,
+    content: &RenderedEmail,
+    author_id: &Uuid,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<StoredDraft, sqlx::Error> {
+    sqlx::query_as!(
+        StoredDraft,
+        r#"
+        INSERT INTO newsletter_issues
+            (id, title, text_content, html_content, author_id, status)
+        VALUES ($1, $2, $3, $4, $5, 'draft')
+        RETURNING id as issue_id, title, text_content, html_content
+        "#,
+        Uuid::new_v4(),
+        title,
+        content.text,
+        content.html,
+        author_id
+    )
+    .fetch_one(postgres_transaction)
+    .await
+}
+
+async fn store_draft_update(
+    issue_id: &Uuid,
+    title: &str,
+    content: &RenderedEmail,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<StoredDraft>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredDraft,
+        r#"
+        UPDATE newsletter_issues
+        SET title = $2, text_content = $3, html_content = $4
+        WHERE id = $1 AND status = 'draft'
+        RETURNING id as issue_id, title, text_content, html_content
+        "#,
+        issue_id,
+        title,
+        content.text,
+        content.html
+    )
+    .fetch_optional(postgres_transaction)
+    .await
+}
+
+async fn get_draft(
+    issue_id: &Uuid,
+    postgres_connection: &mut PgConnection,
+) -> Result<Option<StoredDraft>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredDraft,
+        r#"
+        SELECT id as issue_id, title, text_content, html_content
+        FROM newsletter_issues
+        WHERE id = $1 AND status = 'draft'
+        "#,
+        issue_id
+    )
+    .fetch_optional(postgres_connection)
+    .await
+}
+
+/// The draft row is locked until its delivery tasks are committed, so two
+/// concurrent publications cannot both enqueue them.
+async fn mark_draft_as_published(
+    issue_id: &Uuid,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let published_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published', published_at = $2
+        WHERE id = $1 AND status = 'draft'
+        "#,
+        issue_id,
+        Utc::now()
+    )
+    .execute(postgres_transaction)
+    .await?
+    .rows_affected();
+    Ok(published_rows > 0)
+}
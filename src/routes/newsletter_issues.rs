@@ -0,0 +1,167 @@
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::authentication::authenticate;
+use crate::routes::NewsletterError;
+
+const DEFAULT_PER_PAGE: i64 = 20;
+const MAX_PER_PAGE: i64 = 100;
+
+/// A published issue of the newsletter archive.
+#[derive(Serialize)]
+pub struct NewsletterIssue {
+    issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    author_id: Option<Uuid>,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct Pagination {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct NewsletterIssuesPage {
+    page: i64,
+    per_page: i64,
+    total: i64,
+    issues: Vec<NewsletterIssue>,
+}
+
+#[tracing::instrument(
+name = "Listing newsletter issues",
+skip(pagination, postgres_connection, request),
+fields(
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn newsletter_issues(
+    pagination: web::Query<Pagination>,
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    let page = pagination.page.unwrap_or(1);
+    let per_page = pagination.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if page < 1 {
+        return Err(NewsletterError::ValidationError(format!(
+            "Invalid page: {} must be at least 1",
+            page
+        )));
+    }
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err(NewsletterError::ValidationError(format!(
+            "Invalid per_page: {} must be between 1 and {}",
+            per_page, MAX_PER_PAGE
+        )));
+    }
+
+    let issues = get_published_issues(page, per_page, postgres_connection.as_ref())
+        .await
+        .context("Failed to retrieve newsletter issues")?;
+    let total = count_published_issues(postgres_connection.as_ref())
+        .await
+        .context("Failed to count newsletter issues")?;
+    Ok(HttpResponse::Ok().json(&NewsletterIssuesPage {
+        page,
+        per_page,
+        total,
+        issues,
+    }))
+}
+
+#[tracing::instrument(
+name = "Retrieving newsletter issue",
+skip(postgres_connection, request),
+fields(
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn newsletter_issue(
+    issue_id: web::Path<Uuid>,
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    let issue_id = issue_id.into_inner();
+    let issue = get_published_issue(&issue_id, postgres_connection.as_ref())
+        .await
+        .context("Failed to retrieve newsletter issue")?
+        .ok_or_else(|| {
+            NewsletterError::NotFoundError(format!("Newsletter issue: {} not found", issue_id))
+        })?;
+    Ok(HttpResponse::Ok().json(&issue))
+}
+
+/// Most recently published issues first.
+async fn get_published_issues(
+    page: i64,
+    per_page: i64,
+    postgres_connection: &PgPool,
+) -> Result<Vec<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT id as issue_id, title, text_content, html_content, author_id,
+               published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE status = 'published'
+        ORDER BY published_at DESC, id
+        LIMIT $1 OFFSET $2
+        "#,
+        per_page,
+        (page - 1).saturating_mul(per_page)
+    )
+    .fetch_all(postgres_connection)
+    .await
+}
+
+async fn count_published_issues(postgres_connection: &PgPool) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT count(*) as "count!"
+        FROM newsletter_issues
+        WHERE status = 'published'
+        "#,
+    )
+    .fetch_one(postgres_connection)
+    .await?;
+    Ok(row.count)
+}
+
+async fn get_published_issue(
+    issue_id: &Uuid,
+    postgres_connection: &PgPool,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT id as issue_id, title, text_content, html_content, author_id,
+               published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE id = $1 AND status = 'published'
+        "#,
+        issue_id
+    )
+    .fetch_optional(postgres_connection)
+    .await
+}
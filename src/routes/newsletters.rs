@@ -1,41 +1,115 @@
-use std::convert::TryInto;
+use std::convert::TryFrom;
 
-use actix_web::web::Data;
 use actix_web::{
     web,
     HttpResponse,
 };
 use anyhow::Context;
+use chrono::{
+    DateTime,
+    Utc,
+};
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{
+    PgConnection,
+    PgPool,
+    Postgres,
+    Transaction,
+};
 
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::delivery::{
+    enqueue_delivery_tasks,
+    get_delivery_report,
+    store_issue_attachments,
+};
+use crate::domain::{
+    EmailAttachment,
+    EmailAttachments,
+    IdempotencyKey,
+    MAX_ATTACHMENTS_SIZE,
+};
+use crate::idempotency::{
+    save_response,
+    try_processing,
+    NextAction,
+};
+use crate::routes::authentication::authenticate;
+use crate::routes::scheduled_newsletters::ScheduledIssue;
 use crate::routes::NewsletterError;
-use actix_web::http::HeaderMap;
-use argon2::{
-    Argon2,
-    PasswordHash,
-    PasswordVerifier,
+use crate::templates::{
+    html_to_text,
+    render_markdown,
+    RenderedEmail,
 };
-use std::time::Duration;
+use actix_web::http::HeaderMap;
 use uuid::Uuid;
 
 #[derive(Deserialize)]
 pub struct Article {
     title: String,
     content: ArticleContent,
+    /// When set in the future, the issue is stored and sent at that time.
+    send_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    attachments: Vec<ArticleAttachment>,
+}
+
+/// The content of an issue, a template rendered with the fields of each
+/// subscriber, given either as its HTML and text parts or as Markdown.
+///
+/// Only the [`ISSUE_FIELDS`](crate::templates::ISSUE_FIELDS) are merged, any
+/// other `{{` is sent as is. A missing or blank text part is generated from
+/// the HTML one.
+#[derive(Deserialize)]
+pub struct ArticleContent {
+    pub text: Option<String>,
+    pub html: Option<String>,
+    /// Rendered into the HTML and text parts.
+    pub markdown: Option<String>,
+}
+
+impl ArticleContent {
+    /// The HTML and text parts of the issue.
+    pub fn render(&self) -> Result<RenderedEmail, String> {
+        let content = match (&self.markdown, &self.html, &self.text) {
+            (Some(markdown), None, None) => render_markdown(markdown),
+            (None, Some(html), Some(text)) if !text.trim().is_empty() => RenderedEmail {
+                html: html.clone(),
+                text: text.clone(),
+            },
+            (None, Some(html), _) => RenderedEmail {
+                html: html.clone(),
+                text: html_to_text(html),
+            },
+            (Some(_), ..) => {
+                return Err(
+                    "The content cannot have both markdown and html or text parts".to_string(),
+                )
+            }
+            (None, ..) => return Err("The content requires either markdown or html".to_string()),
+        };
+        Ok(content)
+    }
 }
 
+/// A file sent along with an issue, its content encoded in base64.
 #[derive(Deserialize)]
-struct ArticleContent {
-    text: String,
-    html: String,
+pub struct ArticleAttachment {
+    filename: String,
+    content_type: String,
+    content: String,
+    /// Set for the images displayed inline by the HTML content, which
+    /// references them as `cid:<content_id>`.
+    content_id: Option<String>,
 }
 
+/// The payload limit of the routes accepting an article, large enough for its
+/// attachments once encoded in base64.
+pub const MAX_ARTICLE_SIZE: usize = MAX_ATTACHMENTS_SIZE / 3 * 4 + 1024 * 1024;
+
 #[tracing::instrument(
-name = "Sending newsletter to confirmed users",
-skip(article, postgres_connection, email_client),
+name = "Publishing newsletter to confirmed users",
+skip(article, postgres_connection),
 fields(
 title = % article.title,
 username=tracing::field::Empty,
@@ -45,182 +119,208 @@ uuid=tracing::field::Empty,
 pub async fn newsletters(
     article: web::Json<Article>,
     postgres_connection: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     request: web::HttpRequest,
 ) -> Result<HttpResponse, NewsletterError> {
-    let credentials = get_credentials(request.headers()).map_err(NewsletterError::AuthError)?;
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-    let authenticated_uuid = validate_credentials(credentials, postgres_connection.as_ref())
-        .await
-        .map_err(NewsletterError::AuthError)?;
+    let authenticated_uuid = authenticate(&request, postgres_connection.as_ref()).await?;
+    let idempotency_key =
+        get_idempotency_key(request.headers()).map_err(NewsletterError::ValidationError)?;
+    let content = article
+        .content
+        .render()
+        .map_err(NewsletterError::ValidationError)?;
+    let attachments =
+        parse_attachments(&article.attachments).map_err(NewsletterError::ValidationError)?;
 
-    tracing::Span::current().record("uuid", &tracing::field::display(authenticated_uuid));
-    let confirmed_subscribers = get_confirmed_subscribers(postgres_connection.as_ref())
-        .await
-        .context("Failed to retrieve confirmed subscribers from db")?;
-
-    for subscriber in confirmed_subscribers {
-        match subscriber.email.try_into() {
-            Ok(subscriber_email) => {
-                send_article(&email_client, subscriber_email, &article)
-                    .await
-                    .map_err(|e| tracing::warn!("Error sending new article: {}", e))
-                    .ok();
-            }
-            Err(e) => {
-                tracing::warn!("Invalid email retrieved from db: {}", e)
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => {
+            match try_processing(
+                postgres_connection.as_ref(),
+                idempotency_key,
+                &authenticated_uuid,
+            )
+            .await?
+            {
+                NextAction::StartProcessing(transaction) => *transaction,
+                NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
             }
         }
-    }
+        None => postgres_connection
+            .begin()
+            .await
+            .context("Failed to start SQL transaction to publish a newsletter issue")?,
+    };
+    let scheduled_for = article.send_at.filter(|send_at| *send_at > Utc::now());
+    let issue_id = insert_newsletter_issue(
+        &article,
+        &content,
+        &authenticated_uuid,
+        scheduled_for,
+        &mut transaction,
+    )
+    .await
+    .context("Failed to store newsletter issue")?;
+    store_issue_attachments(&issue_id, &attachments, &mut transaction)
+        .await
+        .context("Failed to store newsletter issue attachments")?;
+    let response = match scheduled_for {
+        Some(send_at) => HttpResponse::Accepted().json(&ScheduledIssue {
+            issue_id,
+            title: article.title.clone(),
+            send_at,
+        }),
+        None => {
+            enqueue_delivery_tasks(&issue_id, &mut transaction)
+                .await
+                .context("Failed to enqueue delivery tasks")?;
+            let delivery_report = get_delivery_report(&issue_id, &mut transaction)
+                .await
+                .context("Failed to build delivery report")?;
+            HttpResponse::Ok().json(&delivery_report)
+        }
+    };
+    let response = match &idempotency_key {
+        Some(idempotency_key) => {
+            save_response(
+                &mut transaction,
+                idempotency_key,
+                &authenticated_uuid,
+                response,
+            )
+            .await?
+        }
+        None => response,
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter issue")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(response)
 }
 
-struct Credentials {
-    username: String,
-    password: String,
+#[tracing::instrument(
+name = "Retrieving newsletter delivery report",
+skip(postgres_connection, request),
+fields(
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn newsletter_report(
+    issue_id: web::Path<Uuid>,
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    let issue_id = issue_id.into_inner();
+
+    let mut connection = postgres_connection
+        .acquire()
+        .await
+        .context("Failed to acquire database connection to build a delivery report")?;
+    if !issue_exists(&issue_id, &mut connection)
+        .await
+        .context("Failed to retrieve newsletter issue")?
+    {
+        return Err(NewsletterError::NotFoundError(format!(
+            "Newsletter issue: {} not found",
+            issue_id
+        )));
+    }
+    let delivery_report = get_delivery_report(&issue_id, &mut connection)
+        .await
+        .context("Failed to build delivery report")?;
+    Ok(HttpResponse::Ok().json(&delivery_report))
 }
 
-#[tracing::instrument(name = "Retrieving user credentials", skip(headers))]
-fn get_credentials(headers: &HeaderMap) -> anyhow::Result<Credentials> {
-    let authorization_header: &str = headers
-        .get("Authorization")
-        .context("Missing `Authorization` header")?
-        .to_str()
-        .context("Invalid `Authorization` content")?;
-    let encoded_credentials = authorization_header
-        .strip_prefix("Basic ")
-        .context("Authorization scheme is not Basic")?;
-    let decoded_credentials_bytes =
-        base64::decode(encoded_credentials).context("Credentials cannot be base64 decoded")?;
-    let decoded_credentials = String::from_utf8(decoded_credentials_bytes)
-        .context("Invalid credentials: not UTF8 chars")?;
-    let mut credentials = decoded_credentials.split(":");
-    let username = credentials
-        .next()
-        .context("Invalid credentials: missing username")?;
-    let password = credentials
-        .next()
-        .context("Invalid credentials: missing password")?;
-    Ok(Credentials {
-        username: username.to_string(),
-        password: password.to_string(),
-    })
+/// Parse the optional `Idempotency-Key` header.
+fn get_idempotency_key(headers: &HeaderMap) -> Result<Option<IdempotencyKey>, String> {
+    headers
+        .get("Idempotency-Key")
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_| "Invalid `Idempotency-Key` content".to_string())
+                .and_then(|key| IdempotencyKey::try_from(key.to_string()))
+        })
+        .transpose()
 }
 
-struct AuthenticatedUser {
-    id: Uuid,
-    phc_password: String,
+/// Decode and validate the attachments of an article.
+pub fn parse_attachments(attachments: &[ArticleAttachment]) -> Result<EmailAttachments, String> {
+    attachments
+        .iter()
+        .map(|attachment| {
+            let content = base64::decode(&attachment.content).map_err(|e| {
+                format!(
+                    "Invalid base64 content for attachment: {}: {}",
+                    attachment.filename, e
+                )
+            })?;
+            EmailAttachment::parse(
+                attachment.filename.clone(),
+                attachment.content_type.clone(),
+                content,
+                attachment.content_id.clone(),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(EmailAttachments::try_from)
 }
 
 #[tracing::instrument(
-    name = "Validating user credentials",
-    skip(credentials, postgres_connection)
+name = "Storing newsletter issue",
+skip(article, content, postgres_transaction),
+fields(
+title = % article.title,
+)
 )]
-async fn validate_credentials(
-    credentials: Credentials,
-    postgres_connection: &PgPool,
-) -> anyhow::Result<Uuid> {
-    let user = retrieve_authenticated_user(&credentials.username, postgres_connection)
-        .await
-        .unwrap_or_else(|_| AuthenticatedUser {
-            id: Default::default(),
-            phc_password: "$argon2id$v=19$m=15000,t=2,p=1$gZiV/M1gPc22ElAH/Jh1Hw$CWOrkoo7oJBQ/\
-                           iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-                .to_string(),
-        });
-    let span = tracing::Span::current();
-    let password = credentials.password;
-    let phc_password = user.phc_password;
-    span.in_scope(|| verify_password(password, phc_password))
-        .await?;
-
-    Ok(user.id)
-}
-async fn retrieve_authenticated_user(
-    username: &str,
-    postgres_connection: &PgPool,
-) -> anyhow::Result<AuthenticatedUser> {
-    sqlx::query_as!(
-        AuthenticatedUser,
+async fn insert_newsletter_issue(
+    article: &Article,
+    content: &RenderedEmail,
+    author_id: &Uuid,
+    scheduled_for: Option<DateTime<Utc>>,
+    postgres_transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Uuid, sqlx::Error> {
+    let issue_id = Uuid::new_v4();
+    let (status, published_at) = match scheduled_for {
+        Some(_) => ("scheduled", None),
+        None => ("published", Some(Utc::now())),
+    };
+    sqlx::query!(
         r#"
-        SELECT id,phc_password
-        FROM users
-        WHERE username=$1
+        INSERT INTO newsletter_issues
+            (id, title, text_content, html_content, author_id, status, scheduled_for,
+             published_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         "#,
-        username,
+        issue_id,
+        article.title,
+        content.text,
+        content.html,
+        author_id,
+        status,
+        scheduled_for,
+        published_at
     )
-    .fetch_optional(postgres_connection)
-    .await
-    .context("Error fetching user from database")?
-    .context("User not found")
-}
-
-async fn verify_password(candidate_password: String, expected_hash: String) -> anyhow::Result<()> {
-    let (sender, receiver) = tokio::sync::oneshot::channel();
-    rayon::spawn(move || {
-        let pwd_hash =
-            PasswordHash::new(&expected_hash).context("Invalid password format: not PHC format");
-        let password_check_result = pwd_hash.and_then(|hash| {
-            Argon2::default()
-                .verify_password(candidate_password.as_bytes(), &hash)
-                .context("Wrong password")
-        });
-
-        sender
-            .send(password_check_result)
-            .map_err(|e| {
-                tracing::warn!("Error sending password check result to the receiver channel");
-                e
-            })
-            .ok();
-    });
-    tokio::time::timeout(Duration::from_secs(1), receiver)
-        .await
-        .context("Error getting password check response: expired timeout (1 seconds)")?
-        .context("Error getting password check response")?
-}
-
-struct ConfirmedSubscriber {
-    email: String,
+    .execute(postgres_transaction)
+    .await?;
+    Ok(issue_id)
 }
 
-#[tracing::instrument(name = "Retrieving confirmed subscribers", skip(postgres_connection))]
-async fn get_confirmed_subscribers(
-    postgres_connection: &PgPool,
-) -> Result<Vec<ConfirmedSubscriber>, sqlx::Error> {
-    let rows = sqlx::query_as!(
-        ConfirmedSubscriber,
+async fn issue_exists(
+    issue_id: &Uuid,
+    postgres_connection: &mut PgConnection,
+) -> Result<bool, sqlx::Error> {
+    let issue = sqlx::query!(
         r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT id
+        FROM newsletter_issues
+        WHERE id = $1
         "#,
+        issue_id
     )
-    .fetch_all(postgres_connection)
+    .fetch_optional(postgres_connection)
     .await?;
-    Ok(rows)
-}
-
-#[tracing::instrument(
-name = "Sending article to confirmed user",
-skip(email_client, article),
-fields(
-title = % article.title,
-)
-)]
-async fn send_article(
-    email_client: &Data<EmailClient>,
-    subscriber: SubscriberEmail,
-    article: &Article,
-) -> Result<(), anyhow::Error> {
-    email_client
-        .send_email(
-            subscriber,
-            &article.title,
-            &article.content.html,
-            &article.content.text,
-        )
-        .await?;
-    Ok(())
+    Ok(issue.is_some())
 }
@@ -0,0 +1,165 @@
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::authentication::authenticate;
+use crate::routes::NewsletterError;
+
+/// An issue waiting for the scheduler to publish it.
+#[derive(Serialize)]
+pub struct ScheduledIssue {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub send_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct Schedule {
+    send_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(
+name = "Listing scheduled newsletters",
+skip(postgres_connection, request),
+fields(
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn scheduled_newsletters(
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    let scheduled_issues = get_scheduled_issues(postgres_connection.as_ref())
+        .await
+        .context("Failed to retrieve scheduled issues")?;
+    Ok(HttpResponse::Ok().json(&scheduled_issues))
+}
+
+#[tracing::instrument(
+name = "Rescheduling newsletter",
+skip(schedule, postgres_connection, request),
+fields(
+send_at = % schedule.send_at,
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn reschedule_newsletter(
+    issue_id: web::Path<Uuid>,
+    schedule: web::Json<Schedule>,
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    if schedule.send_at <= Utc::now() {
+        return Err(NewsletterError::ValidationError(format!(
+            "Invalid send_at: {} is not in the future",
+            schedule.send_at
+        )));
+    }
+    let issue_id = issue_id.into_inner();
+    let scheduled_issue =
+        update_schedule(&issue_id, &schedule.send_at, postgres_connection.as_ref())
+            .await
+            .context("Failed to reschedule issue")?
+            .ok_or_else(|| scheduled_issue_not_found(&issue_id))?;
+    Ok(HttpResponse::Ok().json(&scheduled_issue))
+}
+
+#[tracing::instrument(
+name = "Cancelling scheduled newsletter",
+skip(postgres_connection, request),
+fields(
+username=tracing::field::Empty,
+uuid=tracing::field::Empty,
+)
+)]
+pub async fn cancel_newsletter(
+    issue_id: web::Path<Uuid>,
+    postgres_connection: web::Data<PgPool>,
+    request: web::HttpRequest,
+) -> Result<HttpResponse, NewsletterError> {
+    authenticate(&request, postgres_connection.as_ref()).await?;
+    let issue_id = issue_id.into_inner();
+    if !cancel_schedule(&issue_id, postgres_connection.as_ref())
+        .await
+        .context("Failed to cancel scheduled issue")?
+    {
+        return Err(scheduled_issue_not_found(&issue_id));
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn scheduled_issue_not_found(issue_id: &Uuid) -> NewsletterError {
+    NewsletterError::NotFoundError(format!("Scheduled issue: {} not found", issue_id))
+}
+
+async fn get_scheduled_issues(
+    postgres_connection: &PgPool,
+) -> Result<Vec<ScheduledIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT id as issue_id, title, scheduled_for as "send_at!"
+        FROM newsletter_issues
+        WHERE status = 'scheduled'
+        ORDER BY scheduled_for
+        "#,
+    )
+    .fetch_all(postgres_connection)
+    .await
+}
+
+/// Only issues that have not been published or cancelled yet can be
+/// rescheduled.
+async fn update_schedule(
+    issue_id: &Uuid,
+    send_at: &DateTime<Utc>,
+    postgres_connection: &PgPool,
+) -> Result<Option<ScheduledIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = $2
+        WHERE id = $1 AND status = 'scheduled'
+        RETURNING id as issue_id, title, scheduled_for as "send_at!"
+        "#,
+        issue_id,
+        send_at
+    )
+    .fetch_optional(postgres_connection)
+    .await
+}
+
+async fn cancel_schedule(
+    issue_id: &Uuid,
+    postgres_connection: &PgPool,
+) -> Result<bool, sqlx::Error> {
+    let cancelled_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE id = $1 AND status = 'scheduled'
+        "#,
+        issue_id
+    )
+    .execute(postgres_connection)
+    .await?
+    .rows_affected();
+    Ok(cancelled_rows > 0)
+}
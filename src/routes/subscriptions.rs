@@ -22,8 +22,12 @@ use uuid::Uuid;
 
 use crate::domain::AppBaseUrl;
 use crate::domain::NewSubscriber;
-use crate::email_client::EmailClient;
+use crate::email_client::{
+    Email,
+    EmailSender,
+};
 use crate::routes::NewsletterError;
+use crate::templates::EmailTemplates;
 
 #[derive(Deserialize)]
 pub struct FormData {
@@ -33,7 +37,7 @@ pub struct FormData {
 
 #[tracing::instrument(
 name = "Adding new subscriber",
-skip(form, postgres_connection, email_client),
+skip(form, postgres_connection, email_client, email_templates),
 fields(
 email = % form.email,
 name = % form.name,
@@ -43,8 +47,9 @@ app_base_url = % app_base_url.0
 pub async fn subscribe(
     form: web::Form<FormData>,
     postgres_connection: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailSender>,
     app_base_url: web::Data<AppBaseUrl>,
+    email_templates: web::Data<EmailTemplates>,
 ) -> Result<HttpResponse, NewsletterError> {
     // this error must be explicitly converted to a ValidationError because
     // for String do not implement the Error trait
@@ -68,6 +73,7 @@ pub async fn subscribe(
 
     send_confirmation_email(
         email_client,
+        &email_templates,
         new_subscriber,
         &format!(
             "{}/subscriptions/confirm?subscription_token={}",
@@ -138,26 +144,28 @@ async fn store_token(
 
 #[tracing::instrument(
     name = "Sending confirmation email",
-    skip(email_client, new_subscriber)
+    skip(email_client, email_templates, new_subscriber)
 )]
 async fn send_confirmation_email(
-    email_client: Data<EmailClient>,
+    email_client: Data<dyn EmailSender>,
+    email_templates: &EmailTemplates,
     new_subscriber: NewSubscriber,
     sub_link: &str,
 ) -> Result<(), anyhow::Error> {
+    let confirmation = email_templates.confirmation.render(&[
+        ("name", new_subscriber.name.as_ref()),
+        ("confirm_link", sub_link),
+    ]);
     email_client
-        .send_email(
-            new_subscriber.email,
-            "Newsletter Subscription",
-            &format!(
-                "Welcome to our newsletter!<br />Visit {} to confirm your subscription <br />",
-                sub_link
-            ),
-            &format!(
-                "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-                sub_link
-            ),
-        )
+        .send_email(Email {
+            recipient_name: Some(new_subscriber.name.as_ref()),
+            ..Email::new(
+                new_subscriber.email,
+                "Newsletter Subscription",
+                &confirmation.html,
+                &confirmation.text,
+            )
+        })
         .await?;
     Ok(())
 }
@@ -0,0 +1,101 @@
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::UnsubscribeLinks;
+use crate::routes::NewsletterError;
+use crate::templates::escape_html;
+
+#[derive(Debug, Deserialize)]
+pub struct Parameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+/// The one-click unsubscribe endpoint of RFC 8058, posted to by mail clients
+/// from the `List-Unsubscribe` header of an issue, and by the form of
+/// [`unsubscribe_form`].
+///
+/// It requires no login: the token signs the subscriber id. Unsubscribing
+/// twice succeeds.
+#[tracing::instrument(
+    name = "Unsubscribing subscriber",
+    skip(parameters, postgres_connection, unsubscribe_links),
+    fields(subscriber_id = % parameters.subscriber_id)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<Parameters>,
+    postgres_connection: web::Data<PgPool>,
+    unsubscribe_links: web::Data<UnsubscribeLinks>,
+) -> Result<HttpResponse, NewsletterError> {
+    if !unsubscribe_links.verify(&parameters.subscriber_id, &parameters.token) {
+        return Err(NewsletterError::MissingTokenError(parameters.token.clone()));
+    }
+    unsubscribe_subscriber(&parameters.subscriber_id, postgres_connection.as_ref())
+        .await
+        .context("Failed to unsubscribe subscriber")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The page opened by subscribers from the `{{unsubscribe_link}}` of an
+/// issue, asking them to confirm with a form posted to [`unsubscribe`].
+///
+/// It leaves the subscription unchanged: mail scanners and link prefetchers
+/// open the links of the emails they receive.
+#[tracing::instrument(
+    name = "Rendering unsubscribe form",
+    skip(parameters, unsubscribe_links),
+    fields(subscriber_id = % parameters.subscriber_id)
+)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    unsubscribe_links: web::Data<UnsubscribeLinks>,
+) -> Result<HttpResponse, NewsletterError> {
+    if !unsubscribe_links.verify(&parameters.subscriber_id, &parameters.token) {
+        return Err(NewsletterError::MissingTokenError(parameters.token.clone()));
+    }
+    let action = format!(
+        "/subscriptions/unsubscribe?{}",
+        url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("subscriber_id", &parameters.subscriber_id.to_string())
+            .append_pair("token", &parameters.token)
+            .finish()
+    );
+    let page = [
+        "<!DOCTYPE html>",
+        "<html>",
+        "<head><meta charset=\"utf-8\"><title>Unsubscribe</title></head>",
+        "<body>",
+        &format!("<form method=\"post\" action=\"{}\">", escape_html(&action)),
+        "<input type=\"hidden\" name=\"List-Unsubscribe\" value=\"One-Click\">",
+        "<button type=\"submit\">Unsubscribe from the newsletter</button>",
+        "</form>",
+        "</body>",
+        "</html>",
+        "",
+    ]
+    .join("\n");
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page))
+}
+
+async fn unsubscribe_subscriber(
+    subscriber_id: &Uuid,
+    postgres_connection: &PgPool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed' WHERE id=$1
+        "#,
+        subscriber_id
+    )
+    .execute(postgres_connection)
+    .await?;
+    Ok(())
+}
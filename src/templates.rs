@@ -0,0 +1,9 @@
+pub use email_template::*;
+pub use html_text::html_to_text;
+pub use markdown::render_markdown;
+pub use template::*;
+
+mod email_template;
+mod html_text;
+mod markdown;
+mod template;
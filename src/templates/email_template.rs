@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::templates::Template;
+
+/// The merge fields of the confirmation email.
+pub const CONFIRMATION_FIELDS: [&str; 2] = ["name", "confirm_link"];
+/// The merge fields of the content of an issue, rendered for each subscriber.
+pub const ISSUE_FIELDS: [&str; 2] = ["name", "unsubscribe_link"];
+
+/// The HTML and text variants of an email.
+#[derive(Clone, Debug)]
+pub struct EmailTemplate {
+    html: Template,
+    text: Template,
+}
+
+/// The two parts of an [`EmailTemplate`] with their fields merged.
+#[derive(Debug)]
+pub struct RenderedEmail {
+    pub html: String,
+    pub text: String,
+}
+
+impl EmailTemplate {
+    pub fn parse(html: &str, text: &str, fields: &[&str]) -> Result<Self, String> {
+        Ok(Self {
+            html: Template::html(html, fields)?,
+            text: Template::text(text, fields)?,
+        })
+    }
+
+    /// The content of an issue, which can only use the [`ISSUE_FIELDS`]: its
+    /// other `{{` are text, so that an article can show a code sample.
+    pub fn issue(html: &str, text: &str) -> Self {
+        Self {
+            html: Template::lenient_html(html, &ISSUE_FIELDS),
+            text: Template::lenient_text(text, &ISSUE_FIELDS),
+        }
+    }
+
+    pub fn render(&self, values: &[(&str, &str)]) -> RenderedEmail {
+        RenderedEmail {
+            html: self.html.render(values),
+            text: self.text.render(values),
+        }
+    }
+}
+
+/// The templates of the emails sent by the app itself, loaded from the
+/// `application.templates_directory`.
+#[derive(Clone, Debug)]
+pub struct EmailTemplates {
+    /// Rendered with the [`CONFIRMATION_FIELDS`].
+    pub confirmation: EmailTemplate,
+}
+
+impl EmailTemplates {
+    /// Load the `<template>.html` and `<template>.txt` files of every template
+    /// from `directory`.
+    pub fn load(directory: &Path) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            confirmation: load_template(directory, "confirmation", &CONFIRMATION_FIELDS)?,
+        })
+    }
+}
+
+fn load_template(
+    directory: &Path,
+    name: &str,
+    fields: &[&str],
+) -> Result<EmailTemplate, anyhow::Error> {
+    let read = |extension: &str| {
+        let path = directory.join(format!("{}.{}", name, extension));
+        std::fs::read_to_string(&path).context(format!("Error reading template: {:?}", path))
+    };
+    EmailTemplate::parse(&read("html")?, &read("txt")?, fields)
+        .map_err(anyhow::Error::msg)
+        .context(format!("Invalid template: {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_templates_of_the_repository_are_valid() {
+        let templates = EmailTemplates::load(Path::new("templates")).unwrap();
+
+        let confirmation = templates
+            .confirmation
+            .render(&[("name", "Ursula"), ("confirm_link", "https://any_link")]);
+
+        assert!(confirmation.html.contains("Ursula"));
+        assert!(confirmation.html.contains("https://any_link"));
+        assert!(confirmation.text.contains("Ursula"));
+        assert!(confirmation.text.contains("https://any_link"));
+    }
+
+    #[test]
+    fn issues_only_merge_the_issue_fields() {
+        let issue = EmailTemplate::issue("{{name}} {{confirm_link}}", "{{unsubscribe_link}} {{");
+
+        let rendered = issue.render(&[
+            ("name", "Ursula"),
+            ("unsubscribe_link", "https://any_link"),
+            ("confirm_link", "https://other_link"),
+        ]);
+
+        assert_eq!(rendered.html, "Ursula {{confirm_link}}");
+        assert_eq!(rendered.text, "https://any_link {{");
+    }
+}
@@ -0,0 +1,651 @@
+/// The width the plain-text part is wrapped at, as recommended by RFC 5322.
+pub const TEXT_WIDTH: usize = 78;
+
+/// The elements whose content is not displayed.
+const HIDDEN_ELEMENTS: [&str; 4] = ["head", "script", "style", "title"];
+/// The elements laid out as blocks, ending the text before them.
+const BLOCK_ELEMENTS: [&str; 18] = [
+    "address",
+    "article",
+    "aside",
+    "center",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "header",
+    "main",
+    "nav",
+    "p",
+    "section",
+    "table",
+    "tr",
+];
+
+/// Convert the HTML part of an email into its plain-text part.
+///
+/// Headings are underlined, list items become bullets or numbers, links
+/// become footnotes listed at the end, and the text is wrapped at
+/// [`TEXT_WIDTH`] columns. Preformatted text is kept as is.
+pub fn html_to_text(html: &str) -> String {
+    let mut converter = Converter::default();
+    for token in tokenize(html) {
+        converter.push(token);
+    }
+    converter.finish()
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    End(String),
+    Text(String),
+}
+
+fn tokenize(html: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let tag_start = match rest.find('<') {
+            Some(tag_start) => tag_start,
+            None => {
+                tokens.push(Token::Text(decode_entities(rest)));
+                break;
+            }
+        };
+        if tag_start > 0 {
+            tokens.push(Token::Text(decode_entities(&rest[..tag_start])));
+        }
+        rest = &rest[tag_start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if let Some(end_tag) = rest.strip_prefix("</") {
+            let end = end_tag.find('>').unwrap_or(end_tag.len());
+            tokens.push(Token::End(end_tag[..end].trim().to_lowercase()));
+            rest = end_tag.get(end + 1..).unwrap_or("");
+        } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (name, attributes, tag_end) = parse_start_tag(rest);
+            rest = &rest[tag_end..];
+            // the content of scripts and styles is not HTML
+            if name == "script" || name == "style" {
+                let closing_tag = format!("</{}", name);
+                let content_end =
+                    find_ignoring_ascii_case(rest, &closing_tag).unwrap_or(rest.len());
+                rest = &rest[content_end..];
+            }
+            tokens.push(Token::Start { name, attributes });
+        } else {
+            tokens.push(Token::Text("<".to_string()));
+            rest = &rest[1..];
+        }
+    }
+    tokens
+}
+
+/// The position of `needle`, in ASCII lowercase, in `haystack` compared
+/// without copying it: lowercasing a copy of the rest of the HTML for each
+/// script or style would be quadratic.
+fn find_ignoring_ascii_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Parse the tag starting `html`, returning its name, its attributes and the
+/// position after it.
+///
+/// The delimiters of a tag are ASCII, so it is scanned byte by byte and only
+/// ever sliced next to them.
+fn parse_start_tag(html: &str) -> (String, Vec<(String, String)>, usize) {
+    let bytes = html.as_bytes();
+    let is_name_byte = |b: u8| !b.is_ascii_whitespace() && b != b'>' && b != b'/' && b != b'=';
+    let mut i = 1;
+    while i < bytes.len() && is_name_byte(bytes[i]) {
+        i += 1;
+    }
+    let name = html[1..i].to_lowercase();
+    let mut attributes = Vec::new();
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() {
+            return (name, attributes, html.len());
+        }
+        if bytes[i] == b'>' {
+            return (name, attributes, i + 1);
+        }
+        let attribute_start = i;
+        while i < bytes.len() && is_name_byte(bytes[i]) {
+            i += 1;
+        }
+        let attribute = html[attribute_start..i].to_lowercase();
+        let mut value = String::new();
+        if i < bytes.len() && bytes[i] == b'=' {
+            i += 1;
+            let value_start;
+            let value_end;
+            match bytes.get(i) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    value_start = i + 1;
+                    i = value_start;
+                    while i < bytes.len() && bytes[i] != quote {
+                        i += 1;
+                    }
+                    value_end = i;
+                    i = (i + 1).min(bytes.len());
+                }
+                _ => {
+                    value_start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value_end = i;
+                }
+            }
+            value = decode_entities(&html[value_start..value_end]);
+        }
+        if attribute.is_empty() {
+            // a stray character, such as a lone `=`
+            i += 1;
+        } else {
+            attributes.push((attribute, value));
+        }
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // entities are short, searching further would be quadratic
+        let entity = rest
+            .as_bytes()
+            .iter()
+            .take(11)
+            .position(|b| *b == b';')
+            .and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = match entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => entity.strip_prefix('#')?.parse().ok()?,
+            };
+            std::char::from_u32(code)
+        }
+    }
+}
+
+/// A block of the text, and how it is separated from the previous one.
+struct TextBlock {
+    text: String,
+    /// The outermost list of the block, if any: the items of a list follow
+    /// each other without a blank line.
+    list: Option<usize>,
+    quote_depth: usize,
+}
+
+struct List {
+    /// The number of lists opened before this one.
+    id: usize,
+    ordered: bool,
+    items: usize,
+}
+
+#[derive(Default)]
+struct Converter {
+    blocks: Vec<TextBlock>,
+    inline: String,
+    hidden_depth: usize,
+    pre_depth: usize,
+    quote_depth: usize,
+    heading: Option<usize>,
+    lists: Vec<List>,
+    opened_lists: usize,
+    /// The marker of the list item whose first block has not been written
+    /// yet.
+    item_marker: Option<String>,
+    /// The `href` of every open link, and where its text starts.
+    open_links: Vec<(Option<String>, usize)>,
+    footnotes: Vec<String>,
+}
+
+impl Converter {
+    fn push(&mut self, token: Token) {
+        match token {
+            Token::Text(text) if self.hidden_depth == 0 && self.pre_depth > 0 => {
+                self.inline.push_str(&text)
+            }
+            // only `<br>` breaks lines outside of preformatted text
+            Token::Text(text) if self.hidden_depth == 0 => {
+                self.inline.push_str(&text.replace('\n', " "))
+            }
+            Token::Text(_) => {}
+            Token::Start { name, attributes } => self.start(&name, attributes),
+            Token::End(name) => self.end(&name),
+        }
+    }
+
+    fn start(&mut self, name: &str, attributes: Vec<(String, String)>) {
+        let attribute = |attribute: &str| {
+            attributes
+                .iter()
+                .find(|(name, _)| name == attribute)
+                .map(|(_, value)| value.trim().to_string())
+        };
+        match name {
+            _ if HIDDEN_ELEMENTS.contains(&name) => self.hidden_depth += 1,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                self.heading = name[1..].parse().ok();
+            }
+            "ul" | "ol" => {
+                self.flush();
+                self.lists.push(List {
+                    id: self.opened_lists,
+                    ordered: name == "ol",
+                    items: 0,
+                });
+                self.opened_lists += 1;
+            }
+            "li" => {
+                self.flush();
+                if let Some(list) = self.lists.last_mut() {
+                    list.items += 1;
+                    self.item_marker = Some(match list.ordered {
+                        true => format!("{}. ", list.items),
+                        false => "* ".to_string(),
+                    });
+                }
+            }
+            "blockquote" => {
+                self.flush();
+                self.quote_depth += 1;
+            }
+            "pre" => {
+                self.flush();
+                self.pre_depth += 1;
+            }
+            "br" => self.inline.push('\n'),
+            "hr" => {
+                self.flush();
+                self.push_block("-".repeat(20));
+            }
+            "a" => {
+                let href = attribute("href");
+                self.open_links.push((href, self.inline.len()));
+            }
+            "img" => {
+                if let Some(alt) = attribute("alt").filter(|alt| !alt.is_empty()) {
+                    self.inline.push_str(&alt);
+                }
+            }
+            "td" | "th" => self.inline.push(' '),
+            _ if BLOCK_ELEMENTS.contains(&name) => self.flush(),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        match name {
+            _ if HIDDEN_ELEMENTS.contains(&name) => {
+                self.hidden_depth = self.hidden_depth.saturating_sub(1)
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                self.heading = None;
+            }
+            "ul" | "ol" => {
+                self.flush();
+                self.lists.pop();
+            }
+            "li" => {
+                self.flush();
+                self.item_marker = None;
+            }
+            "blockquote" => {
+                self.flush();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            "pre" => {
+                self.flush();
+                self.pre_depth = self.pre_depth.saturating_sub(1);
+            }
+            "a" => {
+                if let Some((Some(href), text_start)) = self.open_links.pop() {
+                    let text = self.inline.get(text_start..).unwrap_or("").trim();
+                    if !text.is_empty() && text != href && is_footnote(&href) {
+                        self.footnotes.push(href);
+                        self.inline
+                            .push_str(&format!(" [{}]", self.footnotes.len()));
+                    }
+                }
+            }
+            _ if BLOCK_ELEMENTS.contains(&name) => self.flush(),
+            _ => {}
+        }
+    }
+
+    /// Write the text since the last block as a new block.
+    fn flush(&mut self) {
+        let inline = std::mem::take(&mut self.inline);
+        let lines = if self.pre_depth > 0 {
+            inline
+                .trim_matches('\n')
+                .lines()
+                .map(|line| line.trim_end().to_string())
+                .collect::<Vec<_>>()
+        } else {
+            inline
+                .split('\n')
+                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                .collect::<Vec<_>>()
+        };
+        let first = lines.iter().position(|line| !line.is_empty());
+        let last = lines.iter().rposition(|line| !line.is_empty());
+        let lines = match (first, last) {
+            (Some(first), Some(last)) => &lines[first..=last],
+            _ => return,
+        };
+
+        let quote_prefix = "> ".repeat(self.quote_depth);
+        let list_indent = "   ".repeat(self.lists.len().saturating_sub(1));
+        let (first_prefix, rest_prefix) = match self.item_marker.take() {
+            Some(marker) => (
+                format!("{}{}{}", quote_prefix, list_indent, marker),
+                format!(
+                    "{}{}{}",
+                    quote_prefix,
+                    list_indent,
+                    " ".repeat(marker.len())
+                ),
+            ),
+            None if !self.lists.is_empty() => {
+                let indent = format!("{}{}   ", quote_prefix, list_indent);
+                (indent.clone(), indent)
+            }
+            None => (quote_prefix.clone(), quote_prefix),
+        };
+
+        let text = if let Some(level) = self.heading {
+            let heading = lines.join(" ");
+            let underline = if level == 1 { "=" } else { "-" };
+            format!(
+                "{}{}\n{}{}",
+                first_prefix,
+                heading,
+                rest_prefix,
+                underline.repeat(heading.chars().count())
+            )
+        } else if self.pre_depth > 0 {
+            lines
+                .iter()
+                .map(|line| format!("{}{}", rest_prefix, line).trim_end().to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            lines
+                .iter()
+                .enumerate()
+                .map(|(i, line)| match i {
+                    0 => wrap(line, &first_prefix, &rest_prefix),
+                    _ => wrap(line, &rest_prefix, &rest_prefix),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        self.push_block(text);
+    }
+
+    fn push_block(&mut self, text: String) {
+        self.blocks.push(TextBlock {
+            text,
+            list: self.lists.first().map(|list| list.id),
+            quote_depth: self.quote_depth,
+        });
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        let mut text = String::new();
+        let mut previous: Option<&TextBlock> = None;
+        for block in &self.blocks {
+            if let Some(previous) = previous {
+                let quote_depth = previous.quote_depth.min(block.quote_depth);
+                if previous.list.is_some() && previous.list == block.list {
+                    text.push('\n');
+                } else if quote_depth > 0 {
+                    text.push_str(&format!("\n{}\n", ">".repeat(quote_depth)));
+                } else {
+                    text.push_str("\n\n");
+                }
+            }
+            text.push_str(&block.text);
+            previous = Some(block);
+        }
+        if !self.footnotes.is_empty() {
+            text.push_str("\n\n");
+            let footnotes = self
+                .footnotes
+                .iter()
+                .enumerate()
+                .map(|(i, href)| format!("[{}] {}", i + 1, href))
+                .collect::<Vec<_>>();
+            text.push_str(&footnotes.join("\n"));
+        }
+        text
+    }
+}
+
+/// Anchors and inline images are not worth a footnote.
+fn is_footnote(href: &str) -> bool {
+    !href.is_empty() && !href.starts_with('#') && !href.to_lowercase().starts_with("cid:")
+}
+
+/// Wrap `line` at [`TEXT_WIDTH`] columns, prefixes included. Words longer
+/// than a line, such as URLs, are never split.
+fn wrap(line: &str, first_prefix: &str, rest_prefix: &str) -> String {
+    let mut wrapped = first_prefix.to_string();
+    let mut width = first_prefix.chars().count();
+    let mut line_is_empty = true;
+    for word in line.split(' ') {
+        let word_width = word.chars().count();
+        if !line_is_empty && width + 1 + word_width > TEXT_WIDTH {
+            wrapped.push('\n');
+            wrapped.push_str(rest_prefix);
+            width = rest_prefix.chars().count();
+            line_is_empty = true;
+        }
+        if !line_is_empty {
+            wrapped.push(' ');
+            width += 1;
+        }
+        wrapped.push_str(word);
+        width += word_width;
+        line_is_empty = false;
+    }
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_are_underlined_and_paragraphs_separated() {
+        let text = html_to_text(
+            "<html><head><title>Ignored</title><style>p { color: red; \
+             }</style></head><body><h1>Weekly   \
+             news</h1><p>First\nparagraph.</p><h2>Section</h2><p>Second<br>line &amp; \
+             more</p></body></html>",
+        );
+
+        let expected = [
+            "Weekly news",
+            "===========",
+            "",
+            "First paragraph.",
+            "",
+            "Section",
+            "-------",
+            "",
+            "Second",
+            "line & more",
+        ];
+        assert_eq!(text, expected.join("\n"));
+    }
+
+    #[test]
+    fn links_become_footnotes() {
+        let text = html_to_text(
+            "<p>Read <a href=\"https://blog.com/post\">the post</a>, <a \
+             href=\"https://site.com\">https://site.com</a>, <a href=\"#top\">top</a> or <a \
+             href='{{unsubscribe_link}}'>unsubscribe</a>.</p>",
+        );
+
+        let expected = [
+            "Read the post [1], https://site.com, top or unsubscribe [2].",
+            "",
+            "[1] https://blog.com/post",
+            "[2] {{unsubscribe_link}}",
+        ];
+        assert_eq!(text, expected.join("\n"));
+    }
+
+    #[test]
+    fn lists_become_bullets_and_numbers() {
+        let text = html_to_text(
+            "<p>Topics:</p><ul><li>first</li><li>second<ol><li>nested</li><li>again</li></ol></\
+             li></ul><p>After</p>",
+        );
+
+        let expected = [
+            "Topics:",
+            "",
+            "* first",
+            "* second",
+            "   1. nested",
+            "   2. again",
+            "",
+            "After",
+        ];
+        assert_eq!(text, expected.join("\n"));
+    }
+
+    #[test]
+    fn consecutive_lists_are_separated() {
+        let text = html_to_text("<ul><li>first</li></ul><ol><li>one</li></ol>");
+
+        assert_eq!(text, "* first\n\n1. one");
+    }
+
+    #[test]
+    fn long_lines_are_wrapped_at_78_columns() {
+        let words = vec!["word"; 40].join(" ");
+        let text = html_to_text(&format!("<ul><li>{}</li></ul>", words));
+
+        let lines = text.lines().collect::<Vec<_>>();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.chars().count() <= TEXT_WIDTH));
+        assert!(lines[0].starts_with("* word"));
+        assert!(lines[1].starts_with("  word"));
+    }
+
+    #[test]
+    fn quotes_and_preformatted_text_are_kept() {
+        let text = html_to_text(
+            "<blockquote><p>quoted</p><p>twice</p></blockquote><pre>\nfn main() {\n    \
+             run();\n}\n</pre>",
+        );
+
+        let expected = [
+            "> quoted",
+            ">",
+            "> twice",
+            "",
+            "fn main() {",
+            "    run();",
+            "}",
+        ];
+        assert_eq!(text, expected.join("\n"));
+    }
+
+    #[test]
+    fn styles_with_characters_changing_length_when_lowercased_are_skipped() {
+        let text = html_to_text("<style>İİİİİİİİİ</style>éééé");
+
+        assert_eq!(text, "éééé");
+    }
+
+    #[test]
+    fn deeply_nested_elements_are_converted() {
+        let html = format!(
+            "{}deep{}",
+            "<blockquote><b title='é'>".repeat(10_000),
+            "</b></blockquote>".repeat(10_000)
+        );
+
+        let text = html_to_text(&html);
+
+        assert!(text.ends_with("> deep"));
+    }
+
+    #[test]
+    fn many_ampersands_are_converted() {
+        let html = "&".repeat(1_000_000);
+
+        assert_eq!(html_to_text(&html), html);
+    }
+
+    #[test]
+    fn many_styles_are_skipped() {
+        let html = format!("{}text", "<style>a {}</STYLE>".repeat(100_000));
+
+        assert_eq!(html_to_text(&html), "text");
+    }
+
+    #[test]
+    fn images_are_replaced_by_their_alt_text_and_entities_decoded() {
+        let text = html_to_text(
+            "<p><img src=\"cid:logo\" alt=\"Logo\"> &lt;3 &#169; &#x2014; &unknown; AT&T</p>",
+        );
+
+        assert_eq!(text, "Logo <3 © — &unknown; AT&T");
+    }
+}
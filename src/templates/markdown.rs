@@ -0,0 +1,256 @@
+use pulldown_cmark::{
+    html,
+    CowStr,
+    Event,
+    Parser,
+    Tag,
+    TagEnd,
+};
+
+use crate::templates::html_to_text;
+use crate::templates::template::escape_html;
+use crate::templates::RenderedEmail;
+
+/// The URL schemes links and images may use in the HTML part, other links are
+/// rendered as their text.
+const SAFE_URL_PREFIXES: [&str; 5] = ["http://", "https://", "mailto:", "cid:", "{{"];
+
+/// Render a Markdown article into the HTML and text parts of an email.
+///
+/// The article is parsed as CommonMark. Raw HTML is escaped, links and
+/// images whose URL is not safe are rendered as their text, and merge fields
+/// such as `{{name}}` are kept as is, in URLs too. The text part is converted
+/// from the HTML one, as for HTML articles.
+pub fn render_markdown(markdown: &str) -> RenderedEmail {
+    let mut html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut html, safe_events(Parser::new(markdown)).into_iter());
+    let text = html_to_text(&html);
+    RenderedEmail { html, text }
+}
+
+/// The image being rendered, whose alt text is the text of its events.
+struct Image<'a> {
+    url: CowStr<'a>,
+    alt: String,
+}
+
+/// Escape the raw HTML of `events`, and write the links and images
+/// themselves: the HTML writer would percent-encode the braces of merge
+/// fields.
+fn safe_events<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut safe_events = Vec::new();
+    // whether each open link is written
+    let mut links = Vec::new();
+    let mut image: Option<Image> = None;
+    for event in events {
+        if let Some(open_image) = image.as_mut() {
+            match event {
+                Event::End(TagEnd::Image) => {
+                    let Image { url, alt } = image.take().unwrap();
+                    safe_events.push(match is_safe_url(&url) {
+                        true => Event::Html(
+                            format!(
+                                "<img src=\"{}\" alt=\"{}\" />",
+                                escape_html(&url),
+                                escape_html(&alt)
+                            )
+                            .into(),
+                        ),
+                        false => Event::Text(alt.into()),
+                    });
+                }
+                Event::Text(text) | Event::Code(text) => open_image.alt.push_str(&text),
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Html(html) | Event::InlineHtml(html) => safe_events.push(Event::Text(html)),
+            // the escaped HTML reads as a paragraph
+            Event::Start(Tag::HtmlBlock) => safe_events.push(Event::Start(Tag::Paragraph)),
+            Event::End(TagEnd::HtmlBlock) => safe_events.push(Event::End(TagEnd::Paragraph)),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                let is_safe = is_safe_url(&dest_url);
+                if is_safe {
+                    safe_events.push(Event::Html(
+                        format!("<a href=\"{}\">", escape_html(&dest_url)).into(),
+                    ));
+                }
+                links.push(is_safe);
+            }
+            Event::End(TagEnd::Link) => {
+                if links.pop() == Some(true) {
+                    safe_events.push(Event::Html("</a>".into()));
+                }
+            }
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                image = Some(Image {
+                    url: dest_url,
+                    alt: String::new(),
+                })
+            }
+            event => safe_events.push(event),
+        }
+    }
+    safe_events
+}
+
+fn is_safe_url(url: &str) -> bool {
+    let url = url.to_lowercase();
+    SAFE_URL_PREFIXES
+        .iter()
+        .any(|prefix| url.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_rendered_to_html_and_text() {
+        let markdown = [
+            "# Weekly *news*",
+            "",
+            "Hello {{name}},",
+            "welcome!",
+            "",
+            "- first",
+            "- second",
+            "  continued",
+            "",
+            "1. one",
+            "2. two",
+            "",
+            "> quoted",
+            "",
+            "```",
+            "let x = 1;",
+            "```",
+            "",
+            "---",
+        ];
+
+        let rendered = render_markdown(&markdown.join("\n"));
+
+        let html = [
+            "<h1>Weekly <em>news</em></h1>",
+            "<p>Hello {{name}},",
+            "welcome!</p>",
+            "<ul>",
+            "<li>first</li>",
+            "<li>second",
+            "continued</li>",
+            "</ul>",
+            "<ol>",
+            "<li>one</li>",
+            "<li>two</li>",
+            "</ol>",
+            "<blockquote>",
+            "<p>quoted</p>",
+            "</blockquote>",
+            "<pre><code>let x = 1;",
+            "</code></pre>",
+            "<hr />",
+            "",
+        ];
+        assert_eq!(rendered.html, html.join("\n"));
+        let text = [
+            "Weekly news",
+            "===========",
+            "",
+            "Hello {{name}}, welcome!",
+            "",
+            "* first",
+            "* second continued",
+            "",
+            "1. one",
+            "2. two",
+            "",
+            "> quoted",
+            "",
+            "let x = 1;",
+            "",
+            "--------------------",
+        ];
+        assert_eq!(rendered.text, text.join("\n"));
+    }
+
+    #[test]
+    fn inlines_are_rendered_to_html_and_text() {
+        let rendered = render_markdown(
+            "**bold** _em_ `a < b` [site](https://site.com) ![logo](cid:logo) <https://auto.com>",
+        );
+
+        assert_eq!(
+            rendered.html,
+            "<p><strong>bold</strong> <em>em</em> <code>a &lt; b</code> <a \
+             href=\"https://site.com\">site</a> <img src=\"cid:logo\" alt=\"logo\" /> <a \
+             href=\"https://auto.com\">https://auto.com</a></p>\n"
+        );
+        assert_eq!(
+            rendered.text,
+            "bold em a < b site [1] logo https://auto.com\n\n[1] https://site.com"
+        );
+    }
+
+    #[test]
+    fn merge_fields_are_kept_in_links() {
+        let rendered = render_markdown("[Unsubscribe]({{unsubscribe_link}}) {{unsubscribe_link}}");
+
+        assert_eq!(
+            rendered.html,
+            "<p><a href=\"{{unsubscribe_link}}\">Unsubscribe</a> {{unsubscribe_link}}</p>\n"
+        );
+        assert_eq!(
+            rendered.text,
+            "Unsubscribe [1] {{unsubscribe_link}}\n\n[1] {{unsubscribe_link}}"
+        );
+    }
+
+    #[test]
+    fn urls_may_contain_parentheses() {
+        let rendered =
+            render_markdown("[Rust](https://en.wikipedia.org/wiki/Rust_(programming_language))");
+
+        let url = "https://en.wikipedia.org/wiki/Rust_(programming_language)";
+        assert_eq!(
+            rendered.html,
+            format!("<p><a href=\"{}\">Rust</a></p>\n", url)
+        );
+    }
+
+    #[test]
+    fn raw_html_and_unsafe_links_are_neutralised() {
+        let rendered = render_markdown(
+            "Hi <script>alert(1)</script> [click](javascript:alert(1)) ![x](javascript:x)",
+        );
+
+        assert_eq!(
+            rendered.html,
+            "<p>Hi &lt;script&gt;alert(1)&lt;/script&gt; click x</p>\n"
+        );
+        assert_eq!(
+            render_markdown("<div onclick=\"steal()\">hi</div>").html,
+            "<p>&lt;div onclick=\"steal()\"&gt;hi&lt;/div&gt;</p>\n"
+        );
+    }
+
+    #[test]
+    fn escaped_characters_and_unclosed_delimiters_are_literal() {
+        let rendered = render_markdown("\\*not em\\* 2 * 3 snake_case_name [no link]");
+
+        assert_eq!(
+            rendered.html,
+            "<p>*not em* 2 * 3 snake_case_name [no link]</p>\n"
+        );
+    }
+
+    #[test]
+    fn deeply_nested_delimiters_are_rendered() {
+        let markdown = "[".repeat(100_000);
+
+        let rendered = render_markdown(&markdown);
+
+        assert!(rendered.html.contains(&"[".repeat(100_000)));
+    }
+}
@@ -0,0 +1,229 @@
+/// A text with `{{field}}` merge fields, replaced when it is rendered.
+#[derive(Clone, Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+    escape_html: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field(String),
+}
+
+impl Template {
+    /// Parse an HTML template, whose field values are escaped when rendered.
+    ///
+    /// It fails on an unterminated merge field or on a field missing from
+    /// `fields`, so that a typo is reported before anything is sent.
+    pub fn html(source: &str, fields: &[&str]) -> Result<Self, String> {
+        Ok(Self {
+            segments: parse(source, fields, true)?,
+            escape_html: true,
+        })
+    }
+
+    /// Parse a plain text template.
+    ///
+    /// It fails on an unterminated merge field or on a field missing from
+    /// `fields`.
+    pub fn text(source: &str, fields: &[&str]) -> Result<Self, String> {
+        Ok(Self {
+            segments: parse(source, fields, true)?,
+            escape_html: false,
+        })
+    }
+
+    /// Parse an HTML template whose `{{` not starting one of `fields` are
+    /// text, such as those of a code sample in an article.
+    pub fn lenient_html(source: &str, fields: &[&str]) -> Self {
+        Self {
+            segments: parse(source, fields, false).expect("Lenient templates are always parsed"),
+            escape_html: true,
+        }
+    }
+
+    /// Parse a plain text template whose `{{` not starting one of `fields`
+    /// are text.
+    pub fn lenient_text(source: &str, fields: &[&str]) -> Self {
+        Self {
+            segments: parse(source, fields, false).expect("Lenient templates are always parsed"),
+            escape_html: false,
+        }
+    }
+
+    /// Replace every merge field with its value in `values`, or with nothing
+    /// when it has none.
+    pub fn render(&self, values: &[(&str, &str)]) -> String {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                Segment::Field(field) => {
+                    let value = values
+                        .iter()
+                        .find(|(name, _)| name == field)
+                        .map(|(_, value)| *value)
+                        .unwrap_or_default();
+                    if self.escape_html {
+                        rendered.push_str(&escape_html(value));
+                    } else {
+                        rendered.push_str(value);
+                    }
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// The length of the longest merge field, spaces included.
+const MAX_FIELD_LENGTH: usize = 64;
+
+/// Split `source` into its literals and its merge fields.
+///
+/// Unless `strict`, the `{{` not starting one of `fields` are kept in the
+/// literals instead of failing, and parsing never fails.
+fn parse(source: &str, fields: &[&str], strict: bool) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut literal_start = 0;
+    let mut position = 0;
+    // the `}}` following the last `{{`, which also ends the next ones before
+    // it: searching it again for each of them would be quadratic
+    let mut next_end: Option<usize> = None;
+    while let Some(offset) = source[position..].find("{{") {
+        let start = position + offset;
+        let end = match next_end.filter(|end| *end >= start + 2) {
+            Some(end) => end,
+            None => match source[start + 2..].find("}}") {
+                Some(end) => {
+                    next_end = Some(start + 2 + end);
+                    start + 2 + end
+                }
+                None if strict => {
+                    return Err(format!("Unterminated merge field: {}", &source[start..]))
+                }
+                None => break,
+            },
+        };
+        let field = &source[start + 2..end];
+        // trimming every long text between braces would be quadratic
+        let field = match field.len() <= MAX_FIELD_LENGTH {
+            true => field.trim(),
+            false => field,
+        };
+        if fields.contains(&field) {
+            if start > literal_start {
+                segments.push(Segment::Literal(source[literal_start..start].to_string()));
+            }
+            segments.push(Segment::Field(field.to_string()));
+            position = end + 2;
+            literal_start = position;
+        } else if strict {
+            return Err(format!(
+                "Unknown merge field: {{{{{}}}}}, expected one of: {}",
+                field,
+                fields.join(", ")
+            ));
+        } else {
+            // the next brace may start a field, as in `{{{name}}`
+            position = start + 1;
+        }
+    }
+    if literal_start < source.len() {
+        segments.push(Segment::Literal(source[literal_start..].to_string()));
+    }
+    Ok(segments)
+}
+
+pub(crate) fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+
+    use super::*;
+
+    #[test]
+    fn merge_fields_are_replaced_with_their_values() {
+        let template = Template::text("Hi {{name}}, visit {{ link }}.", &["name", "link"]).unwrap();
+
+        let rendered = template.render(&[("name", "Ursula"), ("link", "https://any_link")]);
+
+        assert_eq!(rendered, "Hi Ursula, visit https://any_link.");
+    }
+
+    #[test]
+    fn fields_without_value_are_rendered_empty() {
+        let template = Template::text("Hi {{name}}!", &["name"]).unwrap();
+
+        assert_eq!(template.render(&[]), "Hi !");
+    }
+
+    #[test]
+    fn html_templates_escape_the_values() {
+        let template = Template::html("<p>{{name}}</p>", &["name"]).unwrap();
+        let text_template = Template::text("{{name}}", &["name"]).unwrap();
+
+        let name = [("name", "<b>Tom & \"Jerry\"</b>")];
+
+        assert_eq!(
+            template.render(&name),
+            "<p>&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;</p>"
+        );
+        assert_eq!(text_template.render(&name), "<b>Tom & \"Jerry\"</b>");
+    }
+
+    #[test]
+    fn unknown_merge_fields_are_rejected() {
+        assert_err!(Template::text("Hi {{nmae}}", &["name"]));
+    }
+
+    #[test]
+    fn unterminated_merge_fields_are_rejected() {
+        assert_err!(Template::text("Hi {{name", &["name"]));
+    }
+
+    #[test]
+    fn lenient_templates_keep_other_braces_as_text() {
+        let template = Template::lenient_text(
+            "{{#each items}}{{this}}{{/each}} {{{name}}} {{ name }} format!(\"{{}}\") \
+             {{unterminated",
+            &["name"],
+        );
+
+        assert_eq!(
+            template.render(&[("name", "Ursula")]),
+            "{{#each items}}{{this}}{{/each}} {Ursula} Ursula format!(\"{{}}\") {{unterminated"
+        );
+    }
+
+    #[test]
+    fn lenient_templates_with_many_braces_are_parsed() {
+        let source = format!("{}}}}}", "{{".repeat(100_000));
+
+        let template = Template::lenient_text(&source, &["name"]);
+
+        assert_eq!(template.render(&[]), source);
+    }
+
+    #[test]
+    fn templates_without_merge_fields_are_rendered_as_is() {
+        let template = Template::html("<p>any_html</p>", &[]).unwrap();
+
+        assert_eq!(template.render(&[]), "<p>any_html</p>");
+    }
+}
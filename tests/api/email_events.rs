@@ -0,0 +1,305 @@
+use std::convert::TryFrom;
+
+use reqwest::Url;
+use serde_json::Value;
+use wiremock::matchers::{
+    any,
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+use crate::api::helpers::{
+    create_authenticated_user,
+    insert_confirmed_subscriber,
+    send_authenticated_json_post_request,
+    send_json_post_request,
+    spawn_app,
+    spawn_app_without_delivery_workers,
+    wait_for_pending_deliveries,
+    TestApp,
+};
+use newsletter::delivery::{
+    try_execute_tasks,
+    ExecutionOutcome,
+};
+use newsletter::domain::{
+    SubscriberEmail,
+    UnsubscribeLinks,
+};
+use newsletter::email_client::EmailClient;
+
+async fn send_email_events(test_app: &TestApp, body: &Value) -> reqwest::Response {
+    send_authenticated_json_post_request(
+        &format!("{}/email_events", test_app.address),
+        body,
+        "mailjet",
+        "test-webhook-secret",
+    )
+    .await
+}
+
+async fn get_subscription_status(test_app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch subscription")
+        .status
+}
+
+/// Publish an issue to `email`, delivered with the provider message id `1`.
+async fn publish_issue_to(test_app: &TestApp, email: &str) -> Value {
+    insert_confirmed_subscriber(email, &test_app.pool).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Messages": [{
+                "Status": "success",
+                "To": [{ "Email": email, "MessageID": 1 }]
+            }]
+        })))
+        .up_to_n_times(1)
+        .mount(&test_app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    let response = send_authenticated_json_post_request(
+        &format!("{}/newsletters", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(test_app).await;
+    response.json().await.unwrap()
+}
+
+#[actix_rt::test]
+async fn hard_bounces_are_matched_to_the_delivery_and_mark_the_subscriber_as_bounced() {
+    let test_app = spawn_app().await;
+    let delivery_report = publish_issue_to(&test_app, "subscriber@gmail.com").await;
+
+    let response = send_email_events(
+        &test_app,
+        &serde_json::json!({
+            "event": "bounce",
+            "time": 1430812195,
+            "MessageID": 1,
+            "email": "subscriber@gmail.com",
+            "hard_bounce": true,
+            "error_related_to": "recipient",
+            "error": "user unknown",
+        }),
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    assert_eq!(
+        get_subscription_status(&test_app, "subscriber@gmail.com").await,
+        "bounced"
+    );
+    let delivery_event =
+        sqlx::query!("SELECT event, provider_message_id, issue_id FROM delivery_events")
+            .fetch_one(&test_app.pool)
+            .await
+            .expect("Failed to fetch delivery event");
+    assert_eq!(delivery_event.event, "bounce");
+    assert_eq!(delivery_event.provider_message_id, Some("1".to_string()));
+    assert_eq!(
+        delivery_event.issue_id.map(|issue_id| issue_id.to_string()),
+        delivery_report["issue_id"].as_str().map(str::to_string)
+    );
+}
+
+#[actix_rt::test]
+async fn grouped_events_update_each_subscriber() {
+    let test_app = spawn_app().await;
+    for email in &["spam@gmail.com", "unsub@gmail.com", "blocked@gmail.com"] {
+        insert_confirmed_subscriber(email, &test_app.pool).await;
+    }
+
+    let response = send_email_events(
+        &test_app,
+        &serde_json::json!([
+            { "event": "spam", "email": "spam@gmail.com", "MessageID": 2 },
+            { "event": "unsub", "email": "unsub@gmail.com", "MessageID": 3 },
+            { "event": "blocked", "email": "blocked@gmail.com", "MessageID": 4 },
+        ]),
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    assert_eq!(
+        get_subscription_status(&test_app, "spam@gmail.com").await,
+        "complained"
+    );
+    assert_eq!(
+        get_subscription_status(&test_app, "unsub@gmail.com").await,
+        "unsubscribed"
+    );
+    assert_eq!(
+        get_subscription_status(&test_app, "blocked@gmail.com").await,
+        "blocked"
+    );
+}
+
+#[actix_rt::test]
+async fn soft_bounces_and_other_events_leave_the_subscriber_confirmed() {
+    let test_app = spawn_app().await;
+    insert_confirmed_subscriber("subscriber@gmail.com", &test_app.pool).await;
+
+    let response = send_email_events(
+        &test_app,
+        &serde_json::json!([
+            { "event": "bounce", "email": "subscriber@gmail.com", "hard_bounce": false },
+            { "event": "open", "email": "subscriber@gmail.com" },
+        ]),
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    assert_eq!(
+        get_subscription_status(&test_app, "subscriber@gmail.com").await,
+        "confirmed"
+    );
+    let delivery_events = sqlx::query!("SELECT count(*) FROM delivery_events")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to count delivery events");
+    assert_eq!(delivery_events.count, Some(2));
+}
+
+#[actix_rt::test]
+async fn bounced_subscribers_are_not_sent_later_issues() {
+    let test_app = spawn_app().await;
+    publish_issue_to(&test_app, "subscriber@gmail.com").await;
+    send_email_events(
+        &test_app,
+        &serde_json::json!({
+            "event": "bounce",
+            "MessageID": 1,
+            "email": "subscriber@gmail.com",
+            "hard_bounce": true,
+        }),
+    )
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "title": "next_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    let response = send_authenticated_json_post_request(
+        &format!("{}/newsletters", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let delivery_report: Value = response.json().await.unwrap();
+    assert_eq!(delivery_report["attempted"], 0);
+}
+
+#[actix_rt::test]
+async fn pending_deliveries_to_bounced_subscribers_are_skipped() {
+    let test_app = spawn_app_without_delivery_workers().await;
+    insert_confirmed_subscriber("subscriber@gmail.com", &test_app.pool).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    let response = send_authenticated_json_post_request(
+        &format!("{}/newsletters", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    send_email_events(
+        &test_app,
+        &serde_json::json!({
+            "event": "bounce",
+            "email": "subscriber@gmail.com",
+            "hard_bounce": true,
+        }),
+    )
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let email_client = EmailClient::new(
+        Url::parse(&test_app.email_server.uri()).unwrap(),
+        SubscriberEmail::try_from("newsletter@gmail.com".to_string()).unwrap(),
+        "any_token".to_string(),
+        10,
+    )
+    .unwrap();
+    let unsubscribe_links =
+        UnsubscribeLinks::new(test_app.address.clone(), "any_secret".to_string(), None);
+
+    let outcome = try_execute_tasks(&test_app.pool, &email_client, &unsubscribe_links).await;
+
+    assert!(matches!(outcome, Ok(ExecutionOutcome::TasksCompleted)));
+    let delivery_task = sqlx::query!("SELECT status, last_error FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(delivery_task.status, "skipped");
+    assert_eq!(
+        delivery_task.last_error,
+        Some("The subscriber is bounced".to_string())
+    );
+}
+
+#[actix_rt::test]
+async fn email_events_with_invalid_credentials_are_rejected() {
+    let test_app = spawn_app().await;
+    insert_confirmed_subscriber("subscriber@gmail.com", &test_app.pool).await;
+    let body = serde_json::json!({
+        "event": "spam",
+        "email": "subscriber@gmail.com",
+    });
+
+    let wrong_password_response = send_authenticated_json_post_request(
+        &format!("{}/email_events", test_app.address),
+        &body,
+        "mailjet",
+        "wrong-secret",
+    )
+    .await;
+    let missing_credentials_response =
+        send_json_post_request(&format!("{}/email_events", test_app.address), &body).await;
+
+    assert_eq!(401, wrong_password_response.status());
+    assert_eq!(401, missing_credentials_response.status());
+    assert_eq!(
+        get_subscription_status(&test_app, "subscriber@gmail.com").await,
+        "confirmed"
+    );
+}
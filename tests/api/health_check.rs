@@ -1,7 +1,16 @@
+use serde_json::Value;
 use sqlx::{
     Connection,
     PgConnection,
 };
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
 
 use newsletter::app::load_configuration;
 
@@ -35,5 +44,34 @@ async fn health_check_works() {
         .await
         .expect("Fail to execute request.");
     assert!(response.status().is_success());
-    assert_eq!(Some(0), response.content_length());
+    let health_report: Value = response.json().await.unwrap();
+    assert_eq!(health_report["email_sender"]["circuit_state"], "closed");
+    assert_eq!(
+        health_report["email_sender"]["remaining_quota"]["burst"],
+        50
+    );
+}
+
+#[actix_rt::test]
+async fn health_check_reports_the_open_email_circuit() {
+    let test_app = spawn_app().await;
+    // the circuit opens after 5 failures
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(5)
+        .mount(&test_app.email_server)
+        .await;
+    let subscribe_endpoint = format!("{}/subscriptions", test_app.address);
+
+    for i in 0..6 {
+        let body = format!("name=le%20guin&email=ursula_le_guin_{}%40gmail.com", i);
+        let response = send_post_request(&subscribe_endpoint, body).await;
+        assert_eq!(500, response.status().as_u16());
+    }
+
+    let response = send_get_request(&format!("{}/health_check", test_app.address)).await;
+    assert_eq!(200, response.status().as_u16());
+    let health_report: Value = response.json().await.unwrap();
+    assert_eq!(health_report["email_sender"]["circuit_state"], "open");
 }
@@ -1,3 +1,15 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use argon2::password_hash::SaltString;
+use argon2::{
+    Algorithm,
+    Argon2,
+    Params,
+    PasswordHasher,
+    Version,
+};
+use chrono::Utc;
 use reqwest::{
     Response,
     Url,
@@ -9,14 +21,27 @@ use sqlx::{
     PgPool,
 };
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    MockServer,
+    ResponseTemplate,
+};
 
 use newsletter::app::{
     load_configuration,
     setup_tracing,
     DatabaseSettings,
+    EmailBackend,
+    EmailProviderSettings,
     NewsletterApp,
+    OutboxSettings,
+    Settings,
 };
+use newsletter::email_client::EmailSender;
 
 // ensure the `tracing` is instantiated only once
 lazy_static::lazy_static! {
@@ -27,7 +52,6 @@ pub struct TestApp {
     pub address: String,
     pub pool: PgPool,
     pub email_server: MockServer,
-    pub base_url: String,
     pub port: u16,
 }
 
@@ -36,6 +60,63 @@ pub struct TestApp {
 /// `actix_rt::test` spins up a new runtime at the beginning of each test case
 /// and they shut down at the end of each test case.
 pub async fn spawn_app() -> TestApp {
+    build_test_app(|_| {}, None).await
+}
+
+/// Spawn the app without delivery workers, the delivery tasks stay pending
+/// until the test executes them.
+pub async fn spawn_app_without_delivery_workers() -> TestApp {
+    build_test_app(|c| c.application.delivery_workers = 0, None).await
+}
+
+/// Spawn the app on top of `email_sender` instead of the configured backend.
+pub async fn spawn_app_with_email_sender(email_sender: Arc<dyn EmailSender>) -> TestApp {
+    build_test_app(|_| {}, Some(email_sender)).await
+}
+
+/// Spawn the app with an unreachable primary provider, and a `fallback`
+/// Mailjet provider served at `base_url`.
+pub async fn spawn_app_with_unreachable_primary_provider(base_url: &str) -> TestApp {
+    // nothing listens on the port of a dropped listener
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let unreachable_url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    build_test_app(
+        |c| {
+            c.email_client.base_url = unreachable_url;
+            c.email_client.fallbacks = vec![EmailProviderSettings {
+                name: "fallback".to_string(),
+                backend: EmailBackend::Mailjet,
+                base_url: Some(base_url.to_string()),
+                outbox: None,
+                rate_limit: None,
+                smtp: None,
+                token: None,
+            }];
+        },
+        None,
+    )
+    .await
+}
+
+/// Spawn the app with the outbox backend, writing emails to `directory`.
+pub async fn spawn_app_with_outbox(directory: &Path) -> TestApp {
+    build_test_app(
+        |c| {
+            c.email_client.backend = EmailBackend::Outbox;
+            c.email_client.outbox = Some(OutboxSettings {
+                directory: directory.to_str().unwrap().to_string(),
+            });
+        },
+        None,
+    )
+    .await
+}
+
+async fn build_test_app(
+    configure: impl FnOnce(&mut Settings),
+    email_sender: Option<Arc<dyn EmailSender>>,
+) -> TestApp {
     lazy_static::initialize(&TRACING);
     let email_server = MockServer::start().await;
 
@@ -43,16 +124,23 @@ pub async fn spawn_app() -> TestApp {
         let mut c = load_configuration().unwrap();
         c.database.name = Uuid::new_v4().to_string();
         c.application.port = 0;
+        c.application.delivery_poll_interval_millis = 10;
+        c.application.scheduler_poll_interval_millis = 10;
+        c.email_client.backend = EmailBackend::Mailjet;
         c.email_client.base_url = email_server.uri();
+        // retries are covered by the `EmailClient` unit tests
+        c.email_client.retry.max_attempts = 1;
+        configure(&mut c);
         c
     };
 
-    let base_url = configuration.application.base_url.clone();
     let postgres_pool = setup_test_database(configuration.database.clone()).await;
 
-    let app = NewsletterApp::from(configuration)
-        .await
-        .expect("error building app");
+    let app = match email_sender {
+        Some(email_sender) => NewsletterApp::with_email_sender(configuration, email_sender).await,
+        None => NewsletterApp::from(configuration).await,
+    }
+    .expect("error building app");
 
     let server = app.server;
     tokio::task::spawn_blocking(|| server.expect("error building server"));
@@ -62,11 +150,26 @@ pub async fn spawn_app() -> TestApp {
         address: format!("http://127.0.0.1:{}", app.port),
         pool: postgres_pool,
         email_server,
-        base_url,
         port: app.port,
     }
 }
 
+/// Wait until the delivery workers have completed every pending task.
+pub async fn wait_for_pending_deliveries(test_app: &TestApp) {
+    for _ in 0..500 {
+        let pending_tasks =
+            sqlx::query!("SELECT count(*) FROM issue_delivery_tasks WHERE status = 'pending'")
+                .fetch_one(&test_app.pool)
+                .await
+                .expect("Failed to count pending delivery tasks");
+        if pending_tasks.count == Some(0) {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("Delivery tasks are still pending after 5 seconds");
+}
+
 pub async fn send_post_request(endpoint: &str, body: String) -> Response {
     reqwest::Client::new()
         .post(endpoint)
@@ -92,6 +195,23 @@ pub async fn send_authenticated_json_post_request(
         .expect("Fail to execute post request")
 }
 
+pub async fn send_idempotent_json_post_request(
+    endpoint: &str,
+    body: &Value,
+    username: &str,
+    password: &str,
+    idempotency_key: &str,
+) -> Response {
+    reqwest::Client::new()
+        .post(endpoint)
+        .json(&body)
+        .basic_auth(username, Some(password))
+        .header("Idempotency-Key", idempotency_key)
+        .send()
+        .await
+        .expect("Fail to execute post request")
+}
+
 pub async fn send_json_post_request(endpoint: &str, body: &Value) -> Response {
     reqwest::Client::new()
         .post(endpoint)
@@ -101,6 +221,47 @@ pub async fn send_json_post_request(endpoint: &str, body: &Value) -> Response {
         .expect("Fail to execute post request")
 }
 
+pub async fn send_authenticated_get_request(
+    endpoint: &str,
+    username: &str,
+    password: &str,
+) -> Response {
+    reqwest::Client::new()
+        .get(endpoint)
+        .basic_auth(username, Some(password))
+        .send()
+        .await
+        .expect("Fail to execute get request")
+}
+
+pub async fn send_authenticated_json_put_request(
+    endpoint: &str,
+    body: &Value,
+    username: &str,
+    password: &str,
+) -> Response {
+    reqwest::Client::new()
+        .put(endpoint)
+        .json(&body)
+        .basic_auth(username, Some(password))
+        .send()
+        .await
+        .expect("Fail to execute put request")
+}
+
+pub async fn send_authenticated_delete_request(
+    endpoint: &str,
+    username: &str,
+    password: &str,
+) -> Response {
+    reqwest::Client::new()
+        .delete(endpoint)
+        .basic_auth(username, Some(password))
+        .send()
+        .await
+        .expect("Fail to execute delete request")
+}
+
 pub async fn send_get_request(endpoint: &str) -> Response {
     reqwest::Client::new()
         .get(endpoint)
@@ -130,9 +291,9 @@ async fn setup_test_database(database_settings: DatabaseSettings) -> PgPool {
     connection_pool
 }
 
-pub fn extract_confirmation_links(body: &str) -> Vec<linkify::Link> {
+pub fn extract_confirmation_links(body: &str) -> Vec<linkify::Link<'_>> {
     linkify::LinkFinder::new()
-        .links(&body)
+        .links(body)
         .filter(|link| *link.kind() == linkify::LinkKind::Url)
         .collect::<Vec<_>>()
 }
@@ -163,3 +324,66 @@ pub async fn get_subscription_confirm_url(test_app: &TestApp) -> Url {
         .unwrap();
     subscription_confirm_url
 }
+
+pub async fn create_pending_user(test_app: &TestApp) -> Url {
+    let _mock_guard = Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("create_pending_user")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let subscriptions_endpoint = format!("{}/subscriptions", test_app.address);
+    let subscriptions_body = String::from("name=le%20guin&email=ursula_le_guin%40gmail.com");
+    send_post_request(&subscriptions_endpoint, subscriptions_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    get_subscription_confirm_url(test_app).await
+}
+
+pub async fn create_confirmed_subscriber(test_app: &TestApp) {
+    let subscription_confirm_url = create_pending_user(test_app).await;
+    send_get_request(subscription_confirm_url.as_str())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+pub async fn create_authenticated_user(username: &str, password: &str, pool: &PgPool) {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    // cheap parameters keep concurrent password checks fast in unoptimised test
+    // builds
+    let params = Params::new(1024, 1, 1, None).unwrap();
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.as_ref(), &salt)
+        .unwrap()
+        .to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (id, username, phc_password)
+        VALUES ($1, $2, $3)
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+pub async fn insert_confirmed_subscriber(email: &str, pool: &PgPool) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status, subscribed_at)
+        VALUES ($1, $2, 'any_name', 'confirmed', $3)
+        "#,
+        Uuid::new_v4(),
+        email,
+        Utc::now()
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
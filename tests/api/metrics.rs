@@ -0,0 +1,49 @@
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+use crate::api::helpers::*;
+
+#[actix_rt::test]
+async fn metrics_count_the_calls_to_the_email_provider_by_outcome() {
+    let test_app = spawn_app().await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .mount(&test_app.email_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(400))
+        .mount(&test_app.email_server)
+        .await;
+    let subscribe_endpoint = format!("{}/subscriptions", test_app.address);
+    for i in 0..2 {
+        let body = format!("name=le%20guin&email=ursula_le_guin_{}%40gmail.com", i);
+        send_post_request(&subscribe_endpoint, body).await;
+    }
+
+    let response = send_get_request(&format!("{}/metrics", test_app.address)).await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let metrics = response.text().await.unwrap();
+    assert!(metrics.contains("email_sends_total{provider=\"mailjet\",outcome=\"success\"} 1\n"));
+    assert!(metrics.contains("email_sends_total{provider=\"mailjet\",outcome=\"4xx\"} 1\n"));
+    assert!(metrics.contains("email_sends_total{provider=\"mailjet\",outcome=\"5xx\"} 0\n"));
+    assert!(metrics.contains(
+        "email_send_duration_seconds_count{provider=\"mailjet\",outcome=\"success\"} 1\n"
+    ));
+}
@@ -1,5 +1,11 @@
+mod email_events;
 mod health_check;
 mod helpers;
+mod metrics;
+mod newsletter_drafts;
+mod newsletter_issues;
 mod newsletters;
+mod scheduled_newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
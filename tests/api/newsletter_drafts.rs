@@ -0,0 +1,328 @@
+use serde_json::Value;
+use wiremock::matchers::{
+    any,
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+use crate::api::helpers::{
+    create_authenticated_user,
+    create_confirmed_subscriber,
+    send_authenticated_json_post_request,
+    send_authenticated_json_put_request,
+    send_json_post_request,
+    spawn_app,
+    wait_for_pending_deliveries,
+    TestApp,
+};
+
+async fn create_draft(test_app: &TestApp) -> Value {
+    let body = serde_json::json!({
+        "title": "draft_title",
+        "content": {
+            "text": "draft_text",
+            "html": "draft_html",
+        }
+    });
+    let response = send_authenticated_json_post_request(
+        &format!("{}/newsletters/drafts", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(201, response.status());
+    response.json().await.unwrap()
+}
+
+fn draft_endpoint(test_app: &TestApp, draft: &Value, action: &str) -> String {
+    format!(
+        "{}/newsletters/drafts/{}{}",
+        test_app.address,
+        draft["issue_id"].as_str().unwrap(),
+        action
+    )
+}
+
+#[actix_rt::test]
+async fn drafts_are_not_sent_to_subscribers() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let draft = create_draft(&test_app).await;
+
+    assert_eq!(draft["title"], "draft_title");
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(issue.status, "draft");
+    assert!(issue.published_at.is_none());
+    let delivery_tasks = sqlx::query!("SELECT count(*) FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to count delivery tasks");
+    assert_eq!(delivery_tasks.count, Some(0));
+}
+
+#[actix_rt::test]
+async fn drafts_can_be_edited() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let draft = create_draft(&test_app).await;
+
+    let body = serde_json::json!({
+        "title": "edited_title",
+        "content": {
+            "text": "edited_text",
+            "html": "edited_html",
+        }
+    });
+    let response = send_authenticated_json_put_request(
+        &draft_endpoint(&test_app, &draft, ""),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let issue = sqlx::query!("SELECT title, text_content, html_content FROM newsletter_issues")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(issue.title, "edited_title");
+    assert_eq!(issue.text_content, "edited_text");
+    assert_eq!(issue.html_content, "edited_html");
+}
+
+#[actix_rt::test]
+async fn test_sends_only_reach_the_reviewers() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let draft = create_draft(&test_app).await;
+
+    let response = send_authenticated_json_post_request(
+        &draft_endpoint(&test_app, &draft, "/test"),
+        &serde_json::json!({ "reviewers": ["reviewer@gmail.com"] }),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let test_send_report: Value = response.json().await.unwrap();
+    assert_eq!(test_send_report["sent"], 1);
+    // the first request is the confirmation email of the subscriber
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let email_body: Value =
+        serde_json::from_slice(&received_requests.last().unwrap().body).unwrap();
+    let messages = email_body["Messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["To"][0]["Email"], "reviewer@gmail.com");
+    assert_eq!(messages[0]["Subject"], "draft_title");
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(issue.status, "draft");
+}
+
+#[actix_rt::test]
+async fn test_sends_include_the_draft_attachments() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let draft = create_draft(&test_app).await;
+    let body = serde_json::json!({
+        "title": "edited_title",
+        "content": {
+            "text": "edited_text",
+            "html": "edited_html",
+        },
+        "attachments": [{
+            "filename": "report.pdf",
+            "content_type": "application/pdf",
+            "content": base64::encode("any_pdf"),
+        }]
+    });
+    let response = send_authenticated_json_put_request(
+        &draft_endpoint(&test_app, &draft, ""),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+
+    let response = send_authenticated_json_post_request(
+        &draft_endpoint(&test_app, &draft, "/test"),
+        &serde_json::json!({ "reviewers": ["reviewer@gmail.com"] }),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let email_body: Value = serde_json::from_slice(&received_requests[0].body).unwrap();
+    assert_eq!(
+        email_body["Messages"][0]["Attachments"],
+        serde_json::json!([{
+            "ContentType": "application/pdf",
+            "Filename": "report.pdf",
+            "Base64Content": base64::encode("any_pdf"),
+        }])
+    );
+}
+
+#[actix_rt::test]
+async fn test_sends_with_invalid_reviewers_return_400() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let draft = create_draft(&test_app).await;
+    let test_cases = vec![
+        (serde_json::json!({ "reviewers": [] }), "no reviewer"),
+        (
+            serde_json::json!({ "reviewers": ["reviewer@gmail.com", "not_an_email"] }),
+            "one reviewer is invalid",
+        ),
+        (serde_json::json!({}), "reviewers are missing"),
+    ];
+
+    for (body, error_message) in test_cases {
+        let response = send_authenticated_json_post_request(
+            &draft_endpoint(&test_app, &draft, "/test"),
+            &body,
+            "any_user",
+            "any_password",
+        )
+        .await;
+
+        assert_eq!(
+            400,
+            response.status(),
+            "The API did not fail with 400 when {}",
+            error_message
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn published_drafts_are_sent_to_subscribers() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let draft = create_draft(&test_app).await;
+
+    let response = send_authenticated_json_post_request(
+        &draft_endpoint(&test_app, &draft, "/publish"),
+        &serde_json::json!({}),
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    let delivery_report: Value = response.json().await.unwrap();
+    assert_eq!(delivery_report["issue_id"], draft["issue_id"]);
+    assert_eq!(delivery_report["attempted"], 1);
+    wait_for_pending_deliveries(&test_app).await;
+
+    let delivery_task = sqlx::query!("SELECT status FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(delivery_task.status, "sent");
+
+    // a published issue is no longer a draft
+    let response = send_authenticated_json_post_request(
+        &draft_endpoint(&test_app, &draft, "/publish"),
+        &serde_json::json!({}),
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(404, response.status());
+}
+
+#[actix_rt::test]
+async fn unknown_drafts_return_404() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let draft = serde_json::json!({ "issue_id": uuid::Uuid::new_v4().to_string() });
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+
+    let update_response = send_authenticated_json_put_request(
+        &draft_endpoint(&test_app, &draft, ""),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    let test_response = send_authenticated_json_post_request(
+        &draft_endpoint(&test_app, &draft, "/test"),
+        &serde_json::json!({ "reviewers": ["reviewer@gmail.com"] }),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(404, update_response.status());
+    assert_eq!(404, test_response.status());
+}
+
+#[actix_rt::test]
+async fn drafts_require_authentication() {
+    let test_app = spawn_app().await;
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+
+    let response =
+        send_json_post_request(&format!("{}/newsletters/drafts", test_app.address), &body).await;
+
+    assert_eq!(401, response.status());
+}
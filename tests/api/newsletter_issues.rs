@@ -0,0 +1,210 @@
+use chrono::{
+    Duration,
+    Utc,
+};
+use serde_json::Value;
+use wiremock::matchers::any;
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+use crate::api::helpers::{
+    create_authenticated_user,
+    send_authenticated_get_request,
+    send_authenticated_json_post_request,
+    send_get_request,
+    spawn_app,
+    TestApp,
+};
+
+async fn publish_newsletter(test_app: &TestApp, title: &str) -> Value {
+    let body = serde_json::json!({
+        "title": title,
+        "content": {
+            "text": format!("{} text", title),
+            "html": format!("<p>{}</p>", title),
+        }
+    });
+    let response = send_authenticated_json_post_request(
+        &format!("{}/newsletters", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert!(response.status().is_success());
+    response.json().await.unwrap()
+}
+
+async fn get_user_id(test_app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT id FROM users WHERE username = 'any_user'")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch user")
+        .id
+}
+
+#[actix_rt::test]
+async fn published_issues_are_listed_most_recent_first() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    for title in &["first", "second", "third"] {
+        publish_newsletter(&test_app, title).await;
+    }
+
+    let response = send_authenticated_get_request(
+        &format!("{}/newsletters?page=1&per_page=2", test_app.address),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let issues_page: Value = response.json().await.unwrap();
+    assert_eq!(issues_page["page"], 1);
+    assert_eq!(issues_page["per_page"], 2);
+    assert_eq!(issues_page["total"], 3);
+    let issues = issues_page["issues"].as_array().unwrap();
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[0]["title"], "third");
+    assert_eq!(issues[1]["title"], "second");
+    assert_eq!(
+        issues[0]["author_id"],
+        get_user_id(&test_app).await.to_string()
+    );
+
+    let response = send_authenticated_get_request(
+        &format!("{}/newsletters?page=2&per_page=2", test_app.address),
+        "any_user",
+        "any_password",
+    )
+    .await;
+    let issues_page: Value = response.json().await.unwrap();
+    let issues = issues_page["issues"].as_array().unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["title"], "first");
+}
+
+#[actix_rt::test]
+async fn scheduled_issues_are_not_listed() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        },
+        "send_at": Utc::now() + Duration::hours(1),
+    });
+    send_authenticated_json_post_request(
+        &format!("{}/newsletters", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    let response = send_authenticated_get_request(
+        &format!("{}/newsletters", test_app.address),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let issues_page: Value = response.json().await.unwrap();
+    assert_eq!(issues_page["total"], 0);
+    assert_eq!(issues_page["issues"].as_array().unwrap().len(), 0);
+}
+
+#[actix_rt::test]
+async fn invalid_pagination_returns_400() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let test_cases = vec![
+        ("page=0", "page must be at least 1"),
+        ("per_page=0", "per_page must be at least 1"),
+        ("per_page=101", "per_page must be at most 100"),
+        ("page=not_a_number", "page must be a number"),
+    ];
+
+    for (query, error_message) in test_cases {
+        let response = send_authenticated_get_request(
+            &format!("{}/newsletters?{}", test_app.address, query),
+            "any_user",
+            "any_password",
+        )
+        .await;
+
+        assert_eq!(
+            400,
+            response.status(),
+            "The API did not fail with 400 when {}",
+            error_message
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn a_published_issue_can_be_retrieved() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let delivery_report = publish_newsletter(&test_app, "any_title").await;
+
+    let response = send_authenticated_get_request(
+        &format!(
+            "{}/newsletters/{}",
+            test_app.address,
+            delivery_report["issue_id"].as_str().unwrap()
+        ),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let issue: Value = response.json().await.unwrap();
+    assert_eq!(issue["issue_id"], delivery_report["issue_id"]);
+    assert_eq!(issue["title"], "any_title");
+    assert_eq!(issue["text_content"], "any_title text");
+    assert_eq!(issue["html_content"], "<p>any_title</p>");
+    assert_eq!(issue["author_id"], get_user_id(&test_app).await.to_string());
+    assert!(issue["published_at"].is_string());
+}
+
+#[actix_rt::test]
+async fn unknown_issues_return_404() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+
+    let response = send_authenticated_get_request(
+        &format!("{}/newsletters/{}", test_app.address, uuid::Uuid::new_v4()),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(404, response.status());
+}
+
+#[actix_rt::test]
+async fn the_archive_requires_authentication() {
+    let test_app = spawn_app().await;
+
+    let list_response = send_get_request(&format!("{}/newsletters", test_app.address)).await;
+    let issue_response = send_get_request(&format!(
+        "{}/newsletters/{}",
+        test_app.address,
+        uuid::Uuid::new_v4()
+    ))
+    .await;
+
+    assert_eq!(401, list_response.status());
+    assert_eq!(401, issue_response.status());
+}
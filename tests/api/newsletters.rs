@@ -1,29 +1,48 @@
-use reqwest::Url;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::Duration;
+
+use async_trait::async_trait;
 use wiremock::matchers::{
     any,
+    body_string_contains,
     method,
     path,
 };
 use wiremock::{
     Mock,
+    MockServer,
     ResponseTemplate,
 };
 
 use crate::api::helpers::{
-    get_subscription_confirm_url,
+    create_authenticated_user,
+    create_confirmed_subscriber,
+    create_pending_user,
+    insert_confirmed_subscriber,
+    send_authenticated_get_request,
     send_authenticated_json_post_request,
     send_get_request,
+    send_idempotent_json_post_request,
     send_json_post_request,
-    send_post_request,
     spawn_app,
-    TestApp,
+    spawn_app_with_email_sender,
+    spawn_app_with_unreachable_primary_provider,
+    wait_for_pending_deliveries,
 };
-use argon2::password_hash::SaltString;
-use argon2::{
-    Argon2,
-    PasswordHasher,
+use newsletter::email_client::{
+    Email,
+    EmailSender,
+    NotAttempted,
+    SentEmail,
 };
-use sqlx::PgPool;
+use serde_json::Value;
 use uuid::Uuid;
 
 #[actix_rt::test]
@@ -82,11 +101,38 @@ async fn emails_are_sent_to_confirmed_users() {
     )
     .await;
     assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    let delivery_task = sqlx::query!("SELECT status, attempts FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(delivery_task.status, "sent");
+    assert_eq!(delivery_task.attempts, 1);
 }
 
 #[actix_rt::test]
-async fn requests_missing_authorization_are_rejected() {
-    let newsletters_endpoint = format!("{}/newsletters", spawn_app().await.address);
+async fn provider_message_ids_are_stored_for_each_delivery() {
+    let test_app = spawn_app().await;
+    insert_confirmed_subscriber("subscriber@gmail.com", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Messages": [{
+                "Status": "success",
+                "To": [{
+                    "Email": "subscriber@gmail.com",
+                    "MessageUUID": "1ab23cd4-e567-8901-2345-6789f0gh1i2j",
+                    "MessageID": 1152921504606846976u64,
+                    "MessageHref": "https://api.mailjet.com/v3/message/1152921504606846976",
+                }]
+            }]
+        })))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
     let body = serde_json::json!({
         "title": "any_title",
         "content": {
@@ -94,55 +140,927 @@ async fn requests_missing_authorization_are_rejected() {
             "html": "any_html",
         }
     });
-    let response = send_json_post_request(&newsletters_endpoint, &body).await;
-    assert_eq!(401, response.status());
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    let delivery_task =
+        sqlx::query!("SELECT status, provider_message_id FROM issue_delivery_tasks")
+            .fetch_one(&test_app.pool)
+            .await
+            .expect("Failed to fetch delivery task");
+    assert_eq!(delivery_task.status, "sent");
     assert_eq!(
-        "Basic realm=\"publish\"",
-        response.headers().get("WWW-Authenticate").unwrap()
+        delivery_task.provider_message_id,
+        Some("1152921504606846976".to_string())
+    );
+}
+
+#[actix_rt::test]
+async fn attachments_are_sent_with_the_issue() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // larger than the default payload limit of the JSON routes
+    let report = vec![b'a'; 100 * 1024];
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "<img src=\"cid:logo\">",
+        },
+        "attachments": [
+            {
+                "filename": "report.pdf",
+                "content_type": "application/pdf",
+                "content": base64::encode(&report),
+            },
+            {
+                "filename": "logo.png",
+                "content_type": "image/png",
+                "content": base64::encode("any_png"),
+                "content_id": "logo",
+            },
+        ]
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    // the first request sent the confirmation email of the subscriber
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let email_request = email_requests.last().unwrap();
+    let message = &serde_json::from_slice::<Value>(&email_request.body).unwrap()["Messages"][0];
+    assert_eq!(
+        message["Attachments"],
+        serde_json::json!([{
+            "ContentType": "application/pdf",
+            "Filename": "report.pdf",
+            "Base64Content": base64::encode(&report),
+        }])
+    );
+    assert_eq!(
+        message["InlinedAttachments"],
+        serde_json::json!([{
+            "ContentType": "image/png",
+            "Filename": "logo.png",
+            "ContentID": "logo",
+            "Base64Content": base64::encode("any_png"),
+        }])
     );
 }
 
-pub async fn create_pending_user(test_app: &TestApp) -> Url {
-    let _mock_guard = Mock::given(method("POST"))
+#[actix_rt::test]
+async fn issues_are_addressed_to_the_subscriber_name() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
         .and(path("/send"))
         .respond_with(ResponseTemplate::new(200))
-        .named("create_pending_user")
         .expect(1)
-        .mount_as_scoped(&test_app.email_server)
+        .mount(&test_app.email_server)
         .await;
-    let subscriptions_endpoint = format!("{}/subscriptions", test_app.address);
-    let subscriptions_body = String::from("name=le%20guin&email=ursula_le_guin%40gmail.com");
-    send_post_request(&subscriptions_endpoint, subscriptions_body)
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    let delivery_report: Value = response.json().await.unwrap();
+    wait_for_pending_deliveries(&test_app).await;
+
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&test_app.pool)
         .await
-        .error_for_status()
-        .unwrap();
-    get_subscription_confirm_url(&test_app).await
+        .expect("Failed to fetch subscriber");
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let confirmation_message =
+        &serde_json::from_slice::<Value>(&email_requests[0].body).unwrap()["Messages"][0];
+    let issue_message = &serde_json::from_slice::<Value>(&email_requests.last().unwrap().body)
+        .unwrap()["Messages"][0];
+    for message in [confirmation_message, issue_message].iter() {
+        assert_eq!(message["From"]["Name"], "Newsletter");
+        assert_eq!(
+            message["To"],
+            serde_json::json!([{ "Email": "ursula_le_guin@gmail.com", "Name": "le guin" }])
+        );
+    }
+    assert_eq!(
+        issue_message["CustomID"],
+        format!(
+            "{}:{}",
+            delivery_report["issue_id"].as_str().unwrap(),
+            subscriber.id
+        )
+    );
+}
+
+#[actix_rt::test]
+async fn invalid_attachments_are_rejected() {
+    let test_app = spawn_app().await;
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let invalid_attachments = [
+        (
+            serde_json::json!({
+                "filename": "report.pdf",
+                "content_type": "application/pdf",
+                "content": "not base64",
+            }),
+            "invalid base64 content",
+        ),
+        (
+            serde_json::json!({
+                "filename": "script.sh",
+                "content_type": "application/x-sh",
+                "content": base64::encode("any_script"),
+            }),
+            "unsupported content type",
+        ),
+        (
+            serde_json::json!({
+                "filename": "report.pdf",
+                "content_type": "application/pdf",
+                "content": base64::encode("any_pdf"),
+                "content_id": "report",
+            }),
+            "inline attachment that is not an image",
+        ),
+    ];
+    for (invalid_attachment, error_message) in invalid_attachments.iter() {
+        let body = serde_json::json!({
+            "title": "any_title",
+            "content": {
+                "text": "any_text",
+                "html": "any_html",
+            },
+            "attachments": [invalid_attachment],
+        });
+        let response = send_authenticated_json_post_request(
+            &newsletters_endpoint,
+            &body,
+            "any_user",
+            "any_password",
+        )
+        .await;
+        assert_eq!(
+            400,
+            response.status(),
+            "The API did not fail with 400 Bad Request for an {}",
+            error_message
+        );
+    }
 }
 
-async fn create_confirmed_subscriber(test_app: &TestApp) {
-    let subscription_confirm_url = create_pending_user(test_app).await;
-    send_get_request(subscription_confirm_url.as_str())
+#[actix_rt::test]
+async fn merge_fields_are_rendered_for_each_subscriber() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "Hello {{name}}, leave at {{ unsubscribe_link }}",
+            "html": "<p>Hello {{name}}</p><a href=\"{{unsubscribe_link}}\">Unsubscribe</a>",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&test_app.pool)
         .await
-        .error_for_status()
-        .unwrap();
+        .expect("Failed to fetch subscriber");
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let issue_message = &serde_json::from_slice::<Value>(&email_requests.last().unwrap().body)
+        .unwrap()["Messages"][0];
+    let unsubscribe_link = format!("/subscriptions/unsubscribe?subscriber_id={}", subscriber.id);
+    let text_part = issue_message["TextPart"].as_str().unwrap();
+    assert!(text_part.starts_with("Hello le guin, leave at http"));
+    assert!(text_part.contains(&unsubscribe_link));
+    let html_part = issue_message["HTMLPart"].as_str().unwrap();
+    assert!(html_part.starts_with("<p>Hello le guin</p><a href=\"http"));
+    assert!(html_part.contains(&unsubscribe_link));
 }
 
-async fn create_authenticated_user(username: &str, password: &str, pool: &PgPool) {
-    let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::default()
-        .hash_password(password.as_ref(), &salt)
-        .unwrap()
-        .to_string();
-    sqlx::query!(
-        r#"
-        INSERT INTO users (id, username, phc_password)
-        VALUES ($1, $2, $3)
-        "#,
-        Uuid::new_v4(),
-        username,
-        password_hash,
-    )
-    .execute(pool)
-    .await
-    .unwrap();
+#[actix_rt::test]
+async fn markdown_articles_are_rendered_to_html_and_text() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "markdown": "# News\n\nHello **{{name}}**, read [more](https://blog.com).",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let issue_message = &serde_json::from_slice::<Value>(&email_requests.last().unwrap().body)
+        .unwrap()["Messages"][0];
+    assert_eq!(
+        issue_message["HTMLPart"],
+        "<h1>News</h1>\n<p>Hello <strong>le guin</strong>, read <a \
+         href=\"https://blog.com\">more</a>.</p>\n"
+    );
+    assert_eq!(
+        issue_message["TextPart"],
+        "News\n====\n\nHello le guin, read more [1].\n\n[1] https://blog.com"
+    );
+}
+
+#[actix_rt::test]
+async fn the_text_part_of_html_articles_without_text_is_generated() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "html": "<h1>News</h1><p>Hello {{name}}, read <a \
+                     href=\"https://blog.com\">more</a>.</p>",
+            "text": " ",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    let email_requests = test_app.email_server.received_requests().await.unwrap();
+    let issue_message = &serde_json::from_slice::<Value>(&email_requests.last().unwrap().body)
+        .unwrap()["Messages"][0];
+    let expected_text = [
+        "News",
+        "====",
+        "",
+        "Hello le guin, read more [1].",
+        "",
+        "[1] https://blog.com",
+    ];
+    assert_eq!(issue_message["TextPart"], expected_text.join("\n"));
+}
+
+#[actix_rt::test]
+async fn articles_without_exactly_one_kind_of_content_are_rejected() {
+    let test_app = spawn_app().await;
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let invalid_contents = [
+        (
+            serde_json::json!({"markdown": "any_markdown", "html": "any_html"}),
+            "markdown and html content",
+        ),
+        (
+            serde_json::json!({"text": "any_text"}),
+            "text content without html",
+        ),
+        (serde_json::json!({}), "empty content"),
+    ];
+    for (invalid_content, error_message) in invalid_contents.iter() {
+        let body = serde_json::json!({
+            "title": "any_title",
+            "content": invalid_content,
+        });
+        let response = send_authenticated_json_post_request(
+            &newsletters_endpoint,
+            &body,
+            "any_user",
+            "any_password",
+        )
+        .await;
+        assert_eq!(
+            400,
+            response.status(),
+            "The API did not fail with 400 Bad Request for an {}",
+            error_message
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn braces_of_articles_that_are_not_merge_fields_are_sent_as_is() {
+    let test_app = spawn_app().await;
+    insert_confirmed_subscriber("subscriber@gmail.com", &test_app.pool).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .and(body_string_contains("<p>{{ user.name }} any_name</p>"))
+        .and(body_string_contains("{{confirm_link}} {{name"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "{{confirm_link}} {{name",
+            "html": "<p>{{ user.name }} {{name}}</p>",
+        }
+    });
+    let response = send_authenticated_json_post_request(
+        &format!("{}/newsletters", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+}
+
+#[actix_rt::test]
+async fn deliveries_fail_over_to_the_next_provider_and_record_it() {
+    let fallback_server = MockServer::start().await;
+    let test_app = spawn_app_with_unreachable_primary_provider(&fallback_server.uri()).await;
+    insert_confirmed_subscriber("subscriber@gmail.com", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&fallback_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    let delivery_task = sqlx::query!("SELECT status, provider FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(delivery_task.status, "sent");
+    assert_eq!(delivery_task.provider.as_deref(), Some("fallback"));
+}
+
+#[actix_rt::test]
+async fn subscribers_of_an_issue_are_sent_in_batches() {
+    let test_app = spawn_app().await;
+    for i in 0..3 {
+        insert_confirmed_subscriber(&format!("subscriber_{}@gmail.com", i), &test_app.pool).await;
+    }
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    // concurrent workers may split the issue, but never send one message per call
+    let batch_sizes = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            serde_json::from_slice::<Value>(&request.body).unwrap()["Messages"]
+                .as_array()
+                .unwrap()
+                .len()
+        })
+        .collect::<Vec<_>>();
+    assert!(batch_sizes.len() < 3);
+    assert_eq!(batch_sizes.iter().sum::<usize>(), 3);
+}
+
+#[actix_rt::test]
+async fn failed_deliveries_are_recorded() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    let delivery_task = sqlx::query!("SELECT status, last_error FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(delivery_task.status, "failed");
+    assert!(delivery_task.last_error.is_some());
+}
+
+#[actix_rt::test]
+async fn published_issues_are_stored() {
+    let test_app = spawn_app().await;
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    send_authenticated_json_post_request(&newsletters_endpoint, &body, "any_user", "any_password")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let issue = sqlx::query!("SELECT title, text_content, html_content FROM newsletter_issues")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(issue.title, "any_title");
+    assert_eq!(issue.text_content, "any_text");
+    assert_eq!(issue.html_content, "any_html");
+}
+
+#[actix_rt::test]
+async fn newsletter_creation_is_idempotent() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    for _ in 0..2 {
+        let response = send_idempotent_json_post_request(
+            &newsletters_endpoint,
+            &body,
+            "any_user",
+            "any_password",
+            &idempotency_key,
+        )
+        .await;
+        assert_eq!(200, response.status());
+    }
+    wait_for_pending_deliveries(&test_app).await;
+
+    let issues = sqlx::query!("SELECT count(*) FROM newsletter_issues")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to count newsletter issues");
+    assert_eq!(issues.count, Some(1));
+}
+
+#[actix_rt::test]
+async fn concurrent_requests_with_the_same_idempotency_key_are_sent_once() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let (first_response, second_response) = tokio::join!(
+        send_idempotent_json_post_request(
+            &newsletters_endpoint,
+            &body,
+            "any_user",
+            "any_password",
+            &idempotency_key,
+        ),
+        send_idempotent_json_post_request(
+            &newsletters_endpoint,
+            &body,
+            "any_user",
+            "any_password",
+            &idempotency_key,
+        )
+    );
+    assert_eq!(first_response.status(), second_response.status());
+    assert_eq!(
+        first_response.bytes().await.unwrap(),
+        second_response.bytes().await.unwrap()
+    );
+    wait_for_pending_deliveries(&test_app).await;
+}
+
+#[actix_rt::test]
+async fn invalid_idempotency_keys_are_rejected() {
+    let test_app = spawn_app().await;
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    for invalid_key in ["", &"a".repeat(51)].iter() {
+        let response = send_idempotent_json_post_request(
+            &newsletters_endpoint,
+            &body,
+            "any_user",
+            "any_password",
+            invalid_key,
+        )
+        .await;
+        assert_eq!(400, response.status());
+    }
+}
+
+#[actix_rt::test]
+async fn publishing_returns_a_delivery_report() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    insert_confirmed_subscriber("not-an-email", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    let delivery_report: Value = response.json().await.unwrap();
+    assert_eq!(delivery_report["attempted"], 1);
+    assert_eq!(delivery_report["pending"], 1);
+    assert_eq!(delivery_report["skipped_invalid"], 1);
+    assert_eq!(
+        delivery_report["failures"][0]["subscriber_email"],
+        "not-an-email"
+    );
+    wait_for_pending_deliveries(&test_app).await;
+
+    let report_endpoint = format!(
+        "{}/newsletters/{}/report",
+        test_app.address,
+        delivery_report["issue_id"].as_str().unwrap()
+    );
+    let response =
+        send_authenticated_get_request(&report_endpoint, "any_user", "any_password").await;
+    assert_eq!(200, response.status());
+    let delivery_report: Value = response.json().await.unwrap();
+    assert_eq!(delivery_report["attempted"], 1);
+    assert_eq!(delivery_report["sent"], 1);
+    assert_eq!(delivery_report["failed"], 0);
+    assert_eq!(delivery_report["pending"], 0);
+    assert_eq!(delivery_report["skipped_invalid"], 1);
+}
+
+#[actix_rt::test]
+async fn delivery_report_lists_failures_with_reasons() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let delivery_report: Value = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    wait_for_pending_deliveries(&test_app).await;
+
+    let report_endpoint = format!(
+        "{}/newsletters/{}/report",
+        test_app.address,
+        delivery_report["issue_id"].as_str().unwrap()
+    );
+    let delivery_report: Value =
+        send_authenticated_get_request(&report_endpoint, "any_user", "any_password")
+            .await
+            .json()
+            .await
+            .unwrap();
+    assert_eq!(delivery_report["attempted"], 1);
+    assert_eq!(delivery_report["failed"], 1);
+    assert_eq!(
+        delivery_report["failures"][0]["subscriber_email"],
+        "ursula_le_guin@gmail.com"
+    );
+    assert!(delivery_report["failures"][0]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("500"));
+}
+
+#[actix_rt::test]
+async fn delivery_report_of_unknown_issue_is_not_found() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let report_endpoint = format!("{}/newsletters/{}/report", test_app.address, Uuid::new_v4());
+    let response =
+        send_authenticated_get_request(&report_endpoint, "any_user", "any_password").await;
+    assert_eq!(404, response.status());
+
+    let response = send_get_request(&report_endpoint).await;
+    assert_eq!(401, response.status());
+}
+
+#[actix_rt::test]
+async fn requests_missing_authorization_are_rejected() {
+    let newsletters_endpoint = format!("{}/newsletters", spawn_app().await.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    let response = send_json_post_request(&newsletters_endpoint, &body).await;
+    assert_eq!(401, response.status());
+    assert_eq!(
+        "Basic realm=\"publish\"",
+        response.headers().get("WWW-Authenticate").unwrap()
+    );
+}
+
+/// An `EmailSender` recording recipients instead of sending emails.
+#[derive(Default)]
+struct RecordingEmailSender {
+    recipients: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl EmailSender for RecordingEmailSender {
+    async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+        let mut recipients = self.recipients.lock().unwrap();
+        emails
+            .iter()
+            .map(|email| {
+                recipients.push(email.recipient.as_ref().to_string());
+                Ok(SentEmail::default())
+            })
+            .collect()
+    }
+}
+
+#[actix_rt::test]
+async fn newsletters_are_delivered_through_a_custom_email_sender() {
+    let email_sender = Arc::new(RecordingEmailSender::default());
+    let test_app = spawn_app_with_email_sender(email_sender.clone()).await;
+    insert_confirmed_subscriber("subscriber@gmail.com", &test_app.pool).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    assert_eq!(
+        *email_sender.recipients.lock().unwrap(),
+        vec!["subscriber@gmail.com".to_string()]
+    );
+}
+
+/// An `EmailSender` whose quota is exhausted for its first `exhausted_calls`
+/// calls.
+struct QuotaExhaustedEmailSender {
+    exhausted_calls: usize,
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl EmailSender for QuotaExhaustedEmailSender {
+    async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<Result<SentEmail, anyhow::Error>> {
+        let exhausted = self.calls.fetch_add(1, Ordering::SeqCst) < self.exhausted_calls;
+        emails
+            .iter()
+            .map(|_| match exhausted {
+                true => Err(NotAttempted::QuotaExhausted(Duration::from_secs(60)).into()),
+                false => Ok(SentEmail::default()),
+            })
+            .collect()
+    }
+}
+
+#[actix_rt::test]
+async fn deliveries_are_deferred_while_the_quota_is_exhausted() {
+    let email_sender = Arc::new(QuotaExhaustedEmailSender {
+        exhausted_calls: 3,
+        calls: AtomicUsize::new(0),
+    });
+    let test_app = spawn_app_with_email_sender(email_sender.clone()).await;
+    insert_confirmed_subscriber("subscriber@gmail.com", &test_app.pool).await;
+
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let response = send_authenticated_json_post_request(
+        &format!("{}/newsletters", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(&test_app).await;
+
+    let delivery_task = sqlx::query!("SELECT status, last_error FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(delivery_task.status, "sent");
+    assert_eq!(delivery_task.last_error, None);
+    assert_eq!(email_sender.calls.load(Ordering::SeqCst), 4);
 }
@@ -0,0 +1,223 @@
+use chrono::{
+    Duration,
+    Utc,
+};
+use serde_json::Value;
+use wiremock::matchers::{
+    any,
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+use crate::api::helpers::{
+    create_authenticated_user,
+    create_confirmed_subscriber,
+    send_authenticated_delete_request,
+    send_authenticated_get_request,
+    send_authenticated_json_post_request,
+    send_authenticated_json_put_request,
+    send_get_request,
+    spawn_app,
+    wait_for_pending_deliveries,
+    TestApp,
+};
+
+async fn schedule_newsletter(test_app: &TestApp, send_at: chrono::DateTime<Utc>) -> Value {
+    let newsletters_endpoint = format!("{}/newsletters", test_app.address);
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        },
+        "send_at": send_at,
+    });
+    let response = send_authenticated_json_post_request(
+        &newsletters_endpoint,
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(202, response.status());
+    response.json().await.unwrap()
+}
+
+#[actix_rt::test]
+async fn newsletters_scheduled_in_the_future_are_not_sent_right_away() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let scheduled_issue = schedule_newsletter(&test_app, Utc::now() + Duration::hours(1)).await;
+
+    let response = send_authenticated_get_request(
+        &format!("{}/newsletters/scheduled", test_app.address),
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    let scheduled_issues: Value = response.json().await.unwrap();
+    assert_eq!(scheduled_issues.as_array().unwrap().len(), 1);
+    assert_eq!(scheduled_issues[0]["issue_id"], scheduled_issue["issue_id"]);
+    assert_eq!(scheduled_issues[0]["title"], "any_title");
+    let delivery_tasks = sqlx::query!("SELECT count(*) FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to count delivery tasks");
+    assert_eq!(delivery_tasks.count, Some(0));
+}
+
+#[actix_rt::test]
+async fn scheduled_newsletters_are_sent_when_due() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    schedule_newsletter(&test_app, Utc::now() + Duration::milliseconds(500)).await;
+
+    for _ in 0..500 {
+        let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+            .fetch_one(&test_app.pool)
+            .await
+            .expect("Failed to fetch newsletter issue");
+        if issue.status == "published" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    wait_for_pending_deliveries(&test_app).await;
+
+    let delivery_task = sqlx::query!("SELECT status FROM issue_delivery_tasks")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch delivery task");
+    assert_eq!(delivery_task.status, "sent");
+}
+
+#[actix_rt::test]
+async fn scheduled_newsletters_can_be_rescheduled() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let scheduled_issue = schedule_newsletter(&test_app, Utc::now() + Duration::hours(1)).await;
+    let schedule_endpoint = format!(
+        "{}/newsletters/scheduled/{}",
+        test_app.address,
+        scheduled_issue["issue_id"].as_str().unwrap()
+    );
+
+    let send_at = Utc::now() + Duration::days(1);
+    let response = send_authenticated_json_put_request(
+        &schedule_endpoint,
+        &serde_json::json!({ "send_at": send_at }),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let issue = sqlx::query!("SELECT scheduled_for FROM newsletter_issues")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(
+        issue.scheduled_for.unwrap().timestamp_millis(),
+        send_at.timestamp_millis()
+    );
+}
+
+#[actix_rt::test]
+async fn rescheduling_to_the_past_is_rejected() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let scheduled_issue = schedule_newsletter(&test_app, Utc::now() + Duration::hours(1)).await;
+    let schedule_endpoint = format!(
+        "{}/newsletters/scheduled/{}",
+        test_app.address,
+        scheduled_issue["issue_id"].as_str().unwrap()
+    );
+
+    let response = send_authenticated_json_put_request(
+        &schedule_endpoint,
+        &serde_json::json!({ "send_at": Utc::now() - Duration::hours(1) }),
+        "any_user",
+        "any_password",
+    )
+    .await;
+
+    assert_eq!(400, response.status());
+}
+
+#[actix_rt::test]
+async fn scheduled_newsletters_can_be_cancelled() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let scheduled_issue = schedule_newsletter(&test_app, Utc::now() + Duration::hours(1)).await;
+    let schedule_endpoint = format!(
+        "{}/newsletters/scheduled/{}",
+        test_app.address,
+        scheduled_issue["issue_id"].as_str().unwrap()
+    );
+
+    let response =
+        send_authenticated_delete_request(&schedule_endpoint, "any_user", "any_password").await;
+    assert_eq!(200, response.status());
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch newsletter issue");
+    assert_eq!(issue.status, "cancelled");
+
+    // a cancelled issue is no longer scheduled
+    let response =
+        send_authenticated_delete_request(&schedule_endpoint, "any_user", "any_password").await;
+    assert_eq!(404, response.status());
+}
+
+#[actix_rt::test]
+async fn unknown_scheduled_newsletters_return_404() {
+    let test_app = spawn_app().await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    let schedule_endpoint = format!(
+        "{}/newsletters/scheduled/{}",
+        test_app.address,
+        uuid::Uuid::new_v4()
+    );
+
+    let response = send_authenticated_json_put_request(
+        &schedule_endpoint,
+        &serde_json::json!({ "send_at": Utc::now() + Duration::hours(1) }),
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(404, response.status());
+    let response =
+        send_authenticated_delete_request(&schedule_endpoint, "any_user", "any_password").await;
+    assert_eq!(404, response.status());
+}
+
+#[actix_rt::test]
+async fn scheduled_newsletters_require_authentication() {
+    let test_app = spawn_app().await;
+
+    let response = send_get_request(&format!("{}/newsletters/scheduled", test_app.address)).await;
+
+    assert_eq!(401, response.status());
+}
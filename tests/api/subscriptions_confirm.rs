@@ -1,4 +1,8 @@
-use reqwest::Response;
+use reqwest::{
+    Response,
+    Url,
+};
+use serde_json::Value;
 use uuid::Uuid;
 use wiremock::matchers::{
     method,
@@ -10,10 +14,12 @@ use wiremock::{
 };
 
 use crate::api::helpers::{
+    extract_confirmation_links,
     get_subscription_confirm_url,
     send_get_request,
     send_post_request,
     spawn_app,
+    spawn_app_with_outbox,
     TestApp,
 };
 
@@ -78,18 +84,54 @@ async fn subscriptions_confirm_works() {
     assert_eq!(pending_subscriber.count, Some(0));
 }
 
+#[actix_rt::test]
+async fn confirmation_links_can_be_copied_from_the_outbox() {
+    let outbox_directory = std::env::temp_dir().join(format!("outbox-{}", Uuid::new_v4()));
+    let test_app = spawn_app_with_outbox(&outbox_directory).await;
+
+    let response = send_post_request(
+        &format!("{}/subscriptions", test_app.address),
+        "name=le%20guin&email=ursula_le_guin%40gmail.com".to_string(),
+    )
+    .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let outbox_files = std::fs::read_dir(&outbox_directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(outbox_files.len(), 1);
+    let email: Value = serde_json::from_slice(&std::fs::read(&outbox_files[0]).unwrap()).unwrap();
+    assert_eq!(email["to"], "ursula_le_guin@gmail.com");
+    let text_part = email["text_part"].as_str().unwrap();
+    let mut subscription_confirm_url = Url::parse(
+        extract_confirmation_links(text_part)
+            .first()
+            .unwrap()
+            .as_str(),
+    )
+    .unwrap();
+    subscription_confirm_url
+        .set_port(Some(test_app.port))
+        .unwrap();
+    let response = send_get_request(subscription_confirm_url.as_str()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    std::fs::remove_dir_all(outbox_directory).unwrap();
+}
+
 async fn subscribe_and_confirm(test_app: &TestApp) -> ConfirmRequestDetails {
     let subscribe_end_point = format!("{}/subscriptions", test_app.address);
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com".to_string();
     send_post_request(&subscribe_end_point, body).await;
-    let subscription_confirm_url = get_subscription_confirm_url(&test_app).await;
+    let subscription_confirm_url = get_subscription_confirm_url(test_app).await;
     let subscription_token = subscription_confirm_url
         .query_pairs()
         .next()
         .unwrap()
         .1
         .to_string();
-    let pending_subscriber_id = get_pending_subscriber_id(&test_app, &subscription_token).await;
+    let pending_subscriber_id = get_pending_subscriber_id(test_app, &subscription_token).await;
     let response = send_get_request(subscription_confirm_url.as_str()).await;
     ConfirmRequestDetails {
         response,
@@ -0,0 +1,206 @@
+use reqwest::Url;
+use serde_json::Value;
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+use crate::api::helpers::{
+    create_authenticated_user,
+    create_confirmed_subscriber,
+    send_authenticated_json_post_request,
+    send_get_request,
+    send_post_request,
+    spawn_app,
+    wait_for_pending_deliveries,
+    TestApp,
+};
+
+async fn publish_issue(test_app: &TestApp) {
+    let body = serde_json::json!({
+        "title": "any_title",
+        "content": {
+            "text": "any_text",
+            "html": "any_html",
+        }
+    });
+    let response = send_authenticated_json_post_request(
+        &format!("{}/newsletters", test_app.address),
+        &body,
+        "any_user",
+        "any_password",
+    )
+    .await;
+    assert_eq!(200, response.status());
+    wait_for_pending_deliveries(test_app).await;
+}
+
+/// The headers of the last email sent.
+async fn get_last_email_headers(test_app: &TestApp) -> Value {
+    let received_requests = test_app.email_server.received_requests().await.unwrap();
+    let email_body: Value =
+        serde_json::from_slice(&received_requests.last().unwrap().body).unwrap();
+    email_body["Messages"][0]["Headers"].clone()
+}
+
+/// The one-click unsubscribe URL of the `List-Unsubscribe` header, served by
+/// the test app.
+fn get_unsubscribe_url(test_app: &TestApp, headers: &Value) -> Url {
+    let list_unsubscribe = headers["List-Unsubscribe"].as_str().unwrap();
+    let unsubscribe_url = list_unsubscribe
+        .split(", ")
+        .map(|link| link.trim_start_matches('<').trim_end_matches('>'))
+        .find(|link| link.starts_with("http"))
+        .unwrap();
+    let mut unsubscribe_url = Url::parse(unsubscribe_url).unwrap();
+    unsubscribe_url.set_port(Some(test_app.port)).unwrap();
+    unsubscribe_url
+}
+
+#[actix_rt::test]
+async fn issues_carry_one_click_unsubscribe_headers() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    publish_issue(&test_app).await;
+
+    let headers = get_last_email_headers(&test_app).await;
+    assert_eq!(
+        headers["List-Unsubscribe-Post"],
+        "List-Unsubscribe=One-Click"
+    );
+    let list_unsubscribe = headers["List-Unsubscribe"].as_str().unwrap();
+    assert!(list_unsubscribe.contains("/subscriptions/unsubscribe?subscriber_id="));
+    assert!(list_unsubscribe.contains("<mailto:unsubscribe@gmail.com?subject=unsubscribe-"));
+}
+
+#[actix_rt::test]
+async fn posting_to_the_unsubscribe_url_unsubscribes_without_login() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    publish_issue(&test_app).await;
+    let unsubscribe_url = get_unsubscribe_url(&test_app, &get_last_email_headers(&test_app).await);
+
+    let response = send_post_request(
+        unsubscribe_url.as_str(),
+        "List-Unsubscribe=One-Click".to_string(),
+    )
+    .await;
+
+    assert_eq!(200, response.status());
+    let subscriber = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch subscriber");
+    assert_eq!(subscriber.status, "unsubscribed");
+    // the mock expects a single issue
+    publish_issue(&test_app).await;
+}
+
+#[actix_rt::test]
+async fn opening_the_unsubscribe_link_of_the_content_asks_for_confirmation() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    publish_issue(&test_app).await;
+    let unsubscribe_url = get_unsubscribe_url(&test_app, &get_last_email_headers(&test_app).await);
+
+    let response = send_get_request(unsubscribe_url.as_str()).await;
+
+    assert_eq!(200, response.status());
+    let page = response.text().await.unwrap();
+    assert!(
+        page.contains("<form method=\"post\" action=\"/subscriptions/unsubscribe?subscriber_id=")
+    );
+    let subscriber = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch subscriber");
+    assert_eq!(subscriber.status, "confirmed");
+}
+
+#[actix_rt::test]
+async fn unsubscribing_twice_succeeds() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    publish_issue(&test_app).await;
+    let unsubscribe_url = get_unsubscribe_url(&test_app, &get_last_email_headers(&test_app).await);
+
+    for _ in 0..2 {
+        let response = send_post_request(
+            unsubscribe_url.as_str(),
+            "List-Unsubscribe=One-Click".to_string(),
+        )
+        .await;
+        assert_eq!(200, response.status());
+    }
+}
+
+#[actix_rt::test]
+async fn invalid_unsubscribe_tokens_are_rejected() {
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_authenticated_user("any_user", "any_password", &test_app.pool).await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    publish_issue(&test_app).await;
+    let mut unsubscribe_url =
+        get_unsubscribe_url(&test_app, &get_last_email_headers(&test_app).await);
+    let subscriber_id = unsubscribe_url
+        .query_pairs()
+        .find(|(key, _)| key == "subscriber_id")
+        .unwrap()
+        .1
+        .to_string();
+    unsubscribe_url
+        .query_pairs_mut()
+        .clear()
+        .append_pair("subscriber_id", &subscriber_id)
+        .append_pair("token", "forged_token");
+
+    let response = send_post_request(
+        unsubscribe_url.as_str(),
+        "List-Unsubscribe=One-Click".to_string(),
+    )
+    .await;
+
+    assert_eq!(404, response.status());
+    let subscriber = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.pool)
+        .await
+        .expect("Failed to fetch subscriber");
+    assert_eq!(subscriber.status, "confirmed");
+}